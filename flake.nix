@@ -67,6 +67,7 @@
               { config, pkgs, ... }:
               {
                 environment.systemPackages = with pkgs; [
+                  defaultPackage
                   minio-client
                 ];
                 boot.supportedFilesystems = [
@@ -0,0 +1,93 @@
+use std::{io, path::Path};
+
+use aws_sdk_s3::{
+    error::SdkError,
+    operation::{delete_object::DeleteObjectError, list_objects_v2::ListObjectsV2Error},
+};
+use tokio::fs::remove_file;
+
+use crate::{
+    audit::delete_objects,
+    backup::BackupSaveData,
+    list_objects::list_chunks,
+    run::{AutoBackupState, backup_object_name},
+    save_data::AutoBackupConfig,
+    zfs_commands::{
+        ZfsCommandError, zfs_dataset_name, zfs_destroy_snapshot, zfs_list_snapshots, zfs_release,
+    },
+};
+
+#[derive(Debug)]
+pub enum AbortError {
+    /// There is no backup in progress
+    NotBackingUp,
+    /// The backup is already completely uploaded. Use `run` to finish it instead.
+    AlreadyUploaded,
+    List(SdkError<ListObjectsV2Error>),
+    Delete(SdkError<DeleteObjectError>),
+    Zfs(ZfsCommandError),
+    RemoveFile(io::Error),
+}
+
+/// Stops the backup that is in progress, so that the next run takes a new snapshot.
+/// Deletes the chunks that were uploaded, because the next backup is uploaded to the same object key. Releases the hold on the snapshot and destroys it, and removes the `zfs send` file if `temp_dir` is given.
+/// If `keep_snapshot` is true, the snapshot is kept instead of destroyed, and the next backup will back up this snapshot instead of taking a new one.
+/// Changes `state` so that no backup is in progress. The caller needs to save it.
+pub async fn abort(
+    client: &aws_sdk_s3::Client,
+    AutoBackupConfig {
+        dataset,
+        bucket,
+        snapshot_prefix,
+        object_prefix,
+    }: &AutoBackupConfig<'_>,
+    state: &mut AutoBackupState,
+    temp_dir: Option<&Path>,
+    keep_snapshot: bool,
+) -> Result<(), AbortError> {
+    match state.backing_up_progress {
+        None => return Err(AbortError::NotBackingUp),
+        Some(BackupSaveData::RemovingFile) => return Err(AbortError::AlreadyUploaded),
+        Some(_) => {}
+    }
+    let object_name = backup_object_name(snapshot_prefix, state.snapshots_backed_up);
+    let chunk_keys = list_chunks(client, bucket, &format!("{object_prefix}{object_name}"))
+        .await
+        .map_err(AbortError::List)?
+        .iter()
+        .filter_map(|chunk| chunk.key())
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    delete_objects(client, bucket, &chunk_keys)
+        .await
+        .map_err(AbortError::Delete)?;
+    let dataset_name = zfs_dataset_name(dataset);
+    let snapshot = format!(
+        "{dataset_name}@{snapshot_prefix}{}",
+        state.snapshots_backed_up
+    );
+    // The snapshot won't exist if the backup was aborted before it was taken
+    if zfs_list_snapshots(&dataset_name)
+        .await
+        .map_err(AbortError::Zfs)?
+        .contains(&snapshot)
+    {
+        zfs_release(&snapshot).await.map_err(AbortError::Zfs)?;
+        if !keep_snapshot {
+            zfs_destroy_snapshot(&snapshot)
+                .await
+                .map_err(AbortError::Zfs)?;
+        }
+    }
+    if let Some(temp_dir) = temp_dir {
+        match remove_file(temp_dir.join(&object_name)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(AbortError::RemoveFile(e));
+            }
+            _ => {}
+        }
+    }
+    state.backing_up_progress = None;
+    state.estimated_send_size = None;
+    Ok(())
+}
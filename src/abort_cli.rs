@@ -0,0 +1,70 @@
+use std::{path::PathBuf, process::exit};
+
+use clap::Parser;
+use tokio::fs::read_to_string;
+use zfs_incremental_backup::{
+    abort::{AbortError, abort},
+    lock_save_data::lock_save_data,
+    save_data::parse_save_data,
+    write_save_data::write_save_data,
+};
+
+use crate::s3_client::{S3ClientArgs, create_s3_client};
+
+/// Stops the backup that is in progress, so that the next `run` takes a new snapshot.
+/// Deletes the chunks that were already uploaded and destroys the snapshot that was taken.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[arg(long)]
+    save_data_path: String,
+    /// The `--temp-dir` given to `run`. If set, the `zfs send` file is also deleted.
+    #[arg(long)]
+    temp_dir: Option<PathBuf>,
+    /// Don't destroy the snapshot. The next `run` will back up this snapshot instead of taking a new one.
+    #[arg(long)]
+    keep_snapshot: bool,
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+}
+
+pub async fn abort_cli(
+    Cli {
+        save_data_path,
+        temp_dir,
+        keep_snapshot,
+        s3_client_args,
+    }: Cli,
+) {
+    let _lock = lock_save_data(&save_data_path).unwrap();
+    let mut file_data = parse_save_data(&read_to_string(&save_data_path).await.unwrap()).unwrap();
+    let client = create_s3_client(s3_client_args).await;
+    match abort(
+        &client,
+        &file_data.config,
+        &mut file_data.state,
+        temp_dir.as_deref(),
+        keep_snapshot,
+    )
+    .await
+    {
+        Ok(()) => {}
+        Err(AbortError::NotBackingUp) => {
+            println!("There is no backup in progress");
+            exit(1);
+        }
+        Err(AbortError::AlreadyUploaded) => {
+            println!(
+                "The backup is already completely uploaded and can't be aborted. Use `run` to finish it."
+            );
+            exit(1);
+        }
+        Err(e) => panic!("Failed to abort the backup: {e:?}"),
+    }
+    write_save_data(
+        &save_data_path,
+        &ron::ser::to_string_pretty(&file_data, Default::default()).unwrap(),
+    )
+    .await
+    .unwrap();
+    println!("Aborted the backup");
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use aws_sdk_s3::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::{
+        head_object::HeadObjectError, list_objects_v2::ListObjectsV2Error,
+        restore_object::RestoreObjectError,
+    },
+    types::{GlacierJobParameters, Object, ObjectStorageClass, RestoreRequest, Tier},
+};
+use tokio::time::sleep;
+
+use crate::list_objects::list_chunks;
+
+#[derive(Debug)]
+pub enum ArchiveRestoreError {
+    List(SdkError<ListObjectsV2Error>),
+    /// There are no chunks uploaded for this object key
+    NoChunks(String),
+    Restore(SdkError<RestoreObjectError>),
+    Head(SdkError<HeadObjectError>),
+    /// A restore was never requested for this chunk, so waiting for it would never finish
+    NotRequested(String),
+}
+
+/// Objects in these storage classes need to be restored before they can be downloaded
+fn is_archived(object: &Object) -> bool {
+    matches!(
+        object.storage_class(),
+        Some(ObjectStorageClass::Glacier | ObjectStorageClass::DeepArchive)
+    )
+}
+
+/// Lists the chunks of all of the objects which are archived and need to be restored before downloading
+async fn list_archived_chunks(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    object_keys: &[String],
+) -> Result<Vec<String>, ArchiveRestoreError> {
+    let mut archived_chunks = Vec::new();
+    for object_key in object_keys {
+        let chunks = list_chunks(client, bucket, object_key)
+            .await
+            .map_err(ArchiveRestoreError::List)?;
+        if chunks.is_empty() {
+            return Err(ArchiveRestoreError::NoChunks(object_key.clone()));
+        }
+        archived_chunks.extend(
+            chunks
+                .iter()
+                .filter(|chunk| is_archived(chunk))
+                .filter_map(|chunk| chunk.key())
+                .map(ToOwned::to_owned),
+        );
+    }
+    Ok(archived_chunks)
+}
+
+/// Requests S3 to restore every archived chunk of the objects, so that they can be downloaded.
+/// Chunks that are not in an archive storage class are skipped.
+/// Safe to run again if interrupted, since requesting a restore that is already in progress is not an error.
+/// Returns the number of chunks that restores were requested for.
+pub async fn request_restore(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    object_keys: &[String],
+    days: i32,
+    tier: Tier,
+) -> Result<usize, ArchiveRestoreError> {
+    let archived_chunks = list_archived_chunks(client, bucket, object_keys).await?;
+    for chunk_key in &archived_chunks {
+        let result = client
+            .restore_object()
+            .bucket(bucket)
+            .key(chunk_key)
+            .restore_request(
+                RestoreRequest::builder()
+                    .days(days)
+                    .glacier_job_parameters(
+                        GlacierJobParameters::builder()
+                            .tier(tier.clone())
+                            .build()
+                            .expect("tier is set"),
+                    )
+                    .build(),
+            )
+            .send()
+            .await;
+        match result {
+            Ok(_) => {}
+            Err(e)
+                if e.as_service_error().and_then(|e| e.code())
+                    == Some("RestoreAlreadyInProgress") => {}
+            Err(e) => return Err(ArchiveRestoreError::Restore(e)),
+        }
+    }
+    Ok(archived_chunks.len())
+}
+
+/// Waits until every archived chunk of the objects is restored and can be downloaded.
+/// The restore state is kept by S3, so this can be stopped and started again at any time.
+/// `on_progress` is called with the number of restored chunks and the total number of archived chunks after every check.
+pub async fn wait_for_restore(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    object_keys: &[String],
+    poll_interval: Duration,
+    on_progress: &mut impl FnMut(usize, usize),
+) -> Result<(), ArchiveRestoreError> {
+    let mut pending_chunks = list_archived_chunks(client, bucket, object_keys).await?;
+    let total_chunks = pending_chunks.len();
+    loop {
+        let mut still_pending_chunks = Vec::new();
+        for chunk_key in pending_chunks {
+            let output = client
+                .head_object()
+                .bucket(bucket)
+                .key(&chunk_key)
+                .send()
+                .await
+                .map_err(ArchiveRestoreError::Head)?;
+            // Looks like `ongoing-request="false", expiry-date="..."` once the restore is done
+            match output.restore() {
+                Some(restore) if restore.contains(r#"ongoing-request="false""#) => {}
+                Some(_) => still_pending_chunks.push(chunk_key),
+                None => return Err(ArchiveRestoreError::NotRequested(chunk_key)),
+            }
+        }
+        pending_chunks = still_pending_chunks;
+        on_progress(total_chunks - pending_chunks.len(), total_chunks);
+        if pending_chunks.is_empty() {
+            break Ok(());
+        }
+        sleep(poll_interval).await;
+    }
+}
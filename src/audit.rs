@@ -0,0 +1,262 @@
+use std::{collections::BTreeMap, num::NonZero};
+
+use aws_sdk_s3::{
+    error::SdkError,
+    operation::{delete_object::DeleteObjectError, list_objects_v2::ListObjectsV2Error},
+    types::Object,
+};
+
+use crate::{
+    list_objects::list_objects,
+    run::{AutoBackupState, backup_object_name, parse_backup_object_name},
+    save_data::AutoBackupConfig,
+};
+
+/// Problems found by comparing the objects in the bucket with the save data
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Backups which the save data says were uploaded, but have no objects
+    pub missing_backups: Vec<String>,
+    /// Backups where some of the chunks between `0` and the last chunk are missing
+    pub missing_chunks: Vec<String>,
+    /// Chunks which are not the size that they should be
+    pub wrong_size_chunks: Vec<String>,
+    /// Chunks of backups with this snapshot prefix which are not part of a finished or in progress backup.
+    /// These are usually from backups that were interrupted and then abandoned.
+    pub orphaned_objects: Vec<String>,
+    /// Objects which start with the snapshot prefix but aren't a chunk of a backup with this snapshot prefix,
+    /// such as the backups of another target with a longer snapshot prefix (`backup-offsite0/0` with the prefix `backup`).
+    /// These are never deleted, since they may not have been uploaded by this save data file.
+    pub unrecognized_objects: Vec<String>,
+}
+
+/// Compares the objects under the object prefix with the backups that the save data says were uploaded.
+/// If `chunk_size` is known, also checks that every chunk except the last one of each backup is exactly `chunk_size`.
+pub async fn audit(
+    client: &aws_sdk_s3::Client,
+    config: &AutoBackupConfig<'_>,
+    state: &AutoBackupState,
+    chunk_size: Option<NonZero<usize>>,
+) -> Result<AuditReport, SdkError<ListObjectsV2Error>> {
+    let objects = list_objects(client, &config.bucket, &config.object_prefix).await?;
+    Ok(audit_objects(&objects, config, state, chunk_size))
+}
+
+/// Every object is sorted by the snapshot number in its name, so a missing backup doesn't affect how the later backups are checked.
+/// Objects under the prefix that don't start with the snapshot prefix are ignored, since they weren't uploaded by this program.
+/// Only objects named exactly like a chunk of a backup with this snapshot prefix can be orphans.
+fn audit_objects(
+    objects: &[Object],
+    AutoBackupConfig {
+        snapshot_prefix,
+        object_prefix,
+        ..
+    }: &AutoBackupConfig<'_>,
+    AutoBackupState {
+        snapshots_backed_up,
+        backing_up_progress,
+        ..
+    }: &AutoBackupState,
+    chunk_size: Option<NonZero<usize>>,
+) -> AuditReport {
+    let mut report = AuditReport::default();
+    let mut chunks_by_snapshot_number = BTreeMap::<usize, Vec<(usize, &Object)>>::new();
+    for object in objects {
+        let Some(name) = object
+            .key()
+            .and_then(|key| key.strip_prefix(object_prefix.as_str()))
+        else {
+            continue;
+        };
+        if !name.starts_with(snapshot_prefix.as_str()) {
+            continue;
+        }
+        let chunk = name.rsplit_once('/').and_then(|(object_name, index)| {
+            if index.is_empty() || !index.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            Some((
+                parse_backup_object_name(object_name, snapshot_prefix)?,
+                index.parse::<usize>().ok()?,
+            ))
+        });
+        match chunk {
+            Some((snapshot_number, index)) => chunks_by_snapshot_number
+                .entry(snapshot_number)
+                .or_default()
+                .push((index, object)),
+            None => report
+                .unrecognized_objects
+                .push(object.key().unwrap_or_default().to_owned()),
+        }
+    }
+    // The backup in progress may be partially uploaded, which is fine
+    let backups_to_keep = snapshots_backed_up + usize::from(backing_up_progress.is_some());
+    for snapshot_number in 0..*snapshots_backed_up {
+        if !chunks_by_snapshot_number.contains_key(&snapshot_number) {
+            report
+                .missing_backups
+                .push(backup_object_name(snapshot_prefix, snapshot_number));
+        }
+    }
+    for (snapshot_number, mut chunks) in chunks_by_snapshot_number {
+        if snapshot_number >= backups_to_keep {
+            report.orphaned_objects.extend(
+                chunks
+                    .iter()
+                    .map(|(_, chunk)| chunk.key().unwrap_or_default().to_owned()),
+            );
+            continue;
+        }
+        if snapshot_number >= *snapshots_backed_up {
+            continue;
+        }
+        chunks.sort_by_key(|(index, _)| *index);
+        let is_contiguous = chunks
+            .iter()
+            .enumerate()
+            .all(|(expected_index, (index, _))| *index == expected_index);
+        if !is_contiguous {
+            report
+                .missing_chunks
+                .push(backup_object_name(snapshot_prefix, snapshot_number));
+        }
+        if let Some(chunk_size) = chunk_size {
+            let chunk_size = chunk_size.get() as i64;
+            let last_index = chunks.len() - 1;
+            for (index, (_, chunk)) in chunks.iter().enumerate() {
+                let size = chunk.size().unwrap_or_default();
+                // Only the last chunk can be smaller
+                let is_wrong_size = if index == last_index {
+                    size > chunk_size
+                } else {
+                    size != chunk_size
+                };
+                if is_wrong_size {
+                    report
+                        .wrong_size_chunks
+                        .push(chunk.key().unwrap_or_default().to_owned());
+                }
+            }
+        }
+    }
+    report
+}
+
+/// Deletes objects one at a time
+pub async fn delete_objects(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    object_keys: &[String],
+) -> Result<(), SdkError<DeleteObjectError>> {
+    for object_key in object_keys {
+        client
+            .delete_object()
+            .bucket(bucket)
+            .key(object_key)
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use zfs_wrapper::ZfsDataset;
+
+    use crate::backup::BackupSaveData;
+
+    use super::*;
+
+    fn config() -> AutoBackupConfig<'static> {
+        AutoBackupConfig {
+            dataset: ZfsDataset {
+                zpool: "pool".into(),
+                dataset: "dataset".into(),
+            },
+            bucket: "bucket".to_owned(),
+            snapshot_prefix: "backup".to_owned(),
+            object_prefix: "dataset/".to_owned(),
+        }
+    }
+
+    fn object(key: &str, size: i64) -> Object {
+        Object::builder().key(key).size(size).build()
+    }
+
+    #[test]
+    fn complete_chain() {
+        let objects = [
+            object("dataset/backup0/0", 10),
+            object("dataset/backup0/1", 3),
+            object("dataset/backup0_backup1/0", 7),
+        ];
+        let state = AutoBackupState {
+            snapshots_backed_up: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            audit_objects(&objects, &config(), &state, NonZero::new(10)),
+            AuditReport::default()
+        );
+    }
+
+    #[test]
+    fn gap_in_chain_does_not_orphan_later_backups() {
+        let objects = [
+            object("dataset/backup0/0", 10),
+            object("dataset/backup1_backup2/0", 10),
+            object("dataset/backup2_backup3/0", 10),
+        ];
+        let state = AutoBackupState {
+            snapshots_backed_up: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            audit_objects(&objects, &config(), &state, None),
+            AuditReport {
+                missing_backups: vec!["backup0_backup1".to_owned()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn missing_chunks_wrong_sizes_and_orphans() {
+        let objects = [
+            object("dataset/backup0/0", 10),
+            object("dataset/backup0/2", 10),
+            object("dataset/backup0_backup1/0", 9),
+            object("dataset/backup0_backup1/1", 1),
+            // Part of the backup in progress
+            object("dataset/backup1_backup2/0", 10),
+            // From an abandoned backup
+            object("dataset/backup2_backup3/0", 10),
+            // Another target's backups, or something else which starts with the snapshot prefix
+            object("dataset/backup-offsite0/0", 10),
+            object("dataset/backup-offsite0_backup-offsite1/0", 10),
+            object("dataset/backups/db.sql", 10),
+            // Not uploaded by this program
+            object("dataset/notes.txt", 10),
+        ];
+        let state = AutoBackupState {
+            snapshots_backed_up: 2,
+            backing_up_progress: Some(BackupSaveData::SendingToFile),
+            ..Default::default()
+        };
+        assert_eq!(
+            audit_objects(&objects, &config(), &state, NonZero::new(10)),
+            AuditReport {
+                missing_backups: Vec::new(),
+                missing_chunks: vec!["backup0".to_owned()],
+                wrong_size_chunks: vec!["dataset/backup0_backup1/0".to_owned()],
+                orphaned_objects: vec!["dataset/backup2_backup3/0".to_owned()],
+                unrecognized_objects: vec![
+                    "dataset/backup-offsite0/0".to_owned(),
+                    "dataset/backup-offsite0_backup-offsite1/0".to_owned(),
+                    "dataset/backups/db.sql".to_owned(),
+                ],
+            }
+        );
+    }
+}
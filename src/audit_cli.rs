@@ -0,0 +1,80 @@
+use std::{num::NonZero, process::exit};
+
+use clap::Parser;
+use tokio::fs::read_to_string;
+use zfs_incremental_backup::{
+    audit::{AuditReport, audit, delete_objects},
+    lock_save_data::lock_save_data,
+    save_data::parse_save_data,
+};
+
+use crate::s3_client::{S3ClientArgs, create_s3_client};
+
+/// Compares the objects in the bucket with the save data, to find missing backups, missing or wrongly sized chunks, and leftover objects.
+/// Exits with an error if there are problems.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[arg(long)]
+    save_data_path: String,
+    /// The `--chunk-size` given to `run`. If set, chunks are also checked to be the right size.
+    #[arg(long)]
+    chunk_size: Option<NonZero<usize>>,
+    /// Delete objects that are not part of a finished or in progress backup
+    #[arg(long)]
+    delete_orphans: bool,
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+}
+
+pub async fn audit_cli(
+    Cli {
+        save_data_path,
+        chunk_size,
+        delete_orphans,
+        s3_client_args,
+    }: Cli,
+) {
+    // Make sure that a backup doesn't upload new objects while deleting
+    let _lock = delete_orphans.then(|| lock_save_data(&save_data_path).unwrap());
+    let file_data = parse_save_data(&read_to_string(&save_data_path).await.unwrap()).unwrap();
+    let client = create_s3_client(s3_client_args).await;
+    let AuditReport {
+        missing_backups,
+        missing_chunks,
+        wrong_size_chunks,
+        orphaned_objects,
+        unrecognized_objects,
+    } = audit(&client, &file_data.config, &file_data.state, chunk_size)
+        .await
+        .unwrap();
+    for backup in &missing_backups {
+        println!("Missing backup: {backup}");
+    }
+    for backup in &missing_chunks {
+        println!("Missing chunks: {backup}");
+    }
+    for chunk in &wrong_size_chunks {
+        println!("Wrong size chunk: {chunk}");
+    }
+    for object in &orphaned_objects {
+        println!("Orphaned object: {object}");
+    }
+    // Not a problem by itself, since another target can share the object prefix
+    for object in &unrecognized_objects {
+        println!("Unrecognized object (not deleted): {object}");
+    }
+    if delete_orphans && !orphaned_objects.is_empty() {
+        delete_objects(&client, &file_data.config.bucket, &orphaned_objects)
+            .await
+            .unwrap();
+        println!("Deleted {} orphaned objects", orphaned_objects.len());
+    }
+    let has_problems = !missing_backups.is_empty()
+        || !missing_chunks.is_empty()
+        || !wrong_size_chunks.is_empty()
+        || (!orphaned_objects.is_empty() && !delete_orphans);
+    if has_problems {
+        exit(1);
+    }
+    println!("No problems found");
+}
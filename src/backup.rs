@@ -1,14 +1,19 @@
-use std::{io, num::NonZeroUsize, path::Path};
+use std::{io, num::NonZeroUsize, path::Path, time::Duration};
 
+use fs4::statvfs;
 use rcs3ud::{
     AmountLimiter2, OperationScheduler2, S3Dest, UploadChunkedError2, UploadChunkedSaveData2,
     upload_chunked_2,
 };
 use serde::{Deserialize, Serialize};
-use tokio::fs::{OpenOptions, remove_file};
+use tokio::fs::{OpenOptions, metadata, remove_file};
 
-use zfs_wrapper::{
-    ZfsEnsureSnapshotError, ZfsSendError, ZfsSnapshot, zfs_ensure_snapshot, zfs_send,
+use zfs_wrapper::{ZfsEnsureSnapshotError, ZfsSendError, ZfsSnapshot, zfs_send};
+
+use crate::{
+    ensure_snapshot_with_retry::{SnapshotRetryOptions, zfs_ensure_snapshot_with_retry},
+    zfs_commands::{ZfsCommandError, zfs_hold, zfs_release, zfs_snapshot_name},
+    zfs_send_size_estimate::{ZfsSendSizeEstimateError, zfs_send_size_estimate},
 };
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -20,19 +25,54 @@ pub enum BackupSaveData {
     RemovingFile,
 }
 
-#[allow(unused, clippy::large_enum_variant)]
+/// Something that went wrong without stopping the backup
+#[derive(Debug)]
+pub enum BackupWarning<'a> {
+    /// Taking the snapshot failed, and it will be tried again after `delay`
+    SnapshotRetry {
+        error: &'a ZfsEnsureSnapshotError,
+        delay: Duration,
+        retry: u32,
+    },
+    /// The user doesn't have the `hold` permission, so the snapshot is backed up without a hold
+    HoldDenied(&'a ZfsCommandError),
+    /// The user doesn't have the `release` permission, so the hold was left on the snapshot
+    ReleaseDenied(&'a ZfsCommandError),
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum BackupError<ReserveError, MarkUsedError, SaveError> {
     Snapshot(ZfsEnsureSnapshotError),
+    Hold(ZfsCommandError),
     Save(SaveError),
+    SizeEstimate(ZfsSendSizeEstimateError),
+    Statvfs(io::Error),
+    /// The estimated size of the `zfs send` output is bigger than the free space where the file would be saved
+    NotEnoughSpace {
+        estimated_size: u64,
+        available_space: u64,
+    },
+    /// The estimated size of the `zfs send` output is bigger than the maximum size that was allowed
+    TooBig {
+        estimated_size: u64,
+        max_size: u64,
+    },
     Open(io::Error),
     Send(ZfsSendError),
     Upload(UploadChunkedError2<ReserveError, MarkUsedError, SaveError>),
+    Release(ZfsCommandError),
     RemoveFile(io::Error),
 }
 
 /// Takes a snapshot, does `zfs send -w` to a file, and then uploads the file to S3.
 /// Can be incremental from a previous snapshot.
+/// The snapshot is held while it is being backed up, so that it doesn't get destroyed before it is uploaded.
+/// If the user isn't allowed to hold or release snapshots, the backup continues without the hold, and `on_warning` is called.
+/// Before sending, the size of the `zfs send` output is estimated with a dry run.
+/// The estimate is saved along with the save data, and `estimated_size` is the saved estimate when continuing a previous backup.
+/// If `max_size` is set, the backup stops before sending if the estimate is bigger than it.
+/// Returns the size of the `zfs send` output that was uploaded, in bytes, or `None` if the file was already removed.
 #[allow(clippy::too_many_arguments)]
 pub async fn backup<ReserveError, MarkUsedError, SaveError>(
     mut save_data: BackupSaveData,
@@ -46,16 +86,74 @@ pub async fn backup<ReserveError, MarkUsedError, SaveError>(
     >,
     operation_scheduler: &mut Box<dyn OperationScheduler2 + Send>,
     chunk_size: NonZeroUsize,
-    save: &mut impl AsyncFnMut(&BackupSaveData) -> Result<(), SaveError>,
-) -> Result<(), BackupError<ReserveError, MarkUsedError, SaveError>> {
+    snapshot_retry_options: SnapshotRetryOptions,
+    on_warning: &mut impl FnMut(BackupWarning<'_>),
+    mut estimated_size: Option<u64>,
+    max_size: Option<u64>,
+    save: &mut impl AsyncFnMut(&BackupSaveData, Option<u64>) -> Result<(), SaveError>,
+) -> Result<Option<u64>, BackupError<ReserveError, MarkUsedError, SaveError>> {
+    let snapshot_name = zfs_snapshot_name(&zfs_snapshot);
     if matches!(save_data, BackupSaveData::CreatingSnapshot) {
-        zfs_ensure_snapshot(zfs_snapshot.clone())
-            .await
-            .map_err(BackupError::Snapshot)?;
+        zfs_ensure_snapshot_with_retry(
+            zfs_snapshot.clone(),
+            snapshot_retry_options,
+            &mut |error, delay, retry| {
+                on_warning(BackupWarning::SnapshotRetry {
+                    error,
+                    delay,
+                    retry,
+                })
+            },
+        )
+        .await
+        .map_err(BackupError::Snapshot)?;
+        match zfs_hold(&snapshot_name).await {
+            Ok(()) => {}
+            Err(e) if e.is_permission_denied() => on_warning(BackupWarning::HoldDenied(&e)),
+            Err(e) => return Err(BackupError::Hold(e)),
+        }
         save_data = BackupSaveData::SendingToFile;
-        save(&save_data).await.map_err(BackupError::Save)?;
+        save(&save_data, estimated_size)
+            .await
+            .map_err(BackupError::Save)?;
     }
     if matches!(save_data, BackupSaveData::SendingToFile) {
+        let size = match estimated_size {
+            Some(size) => size,
+            None => {
+                let size = zfs_send_size_estimate(zfs_snapshot.clone(), diff_from)
+                    .await
+                    .map_err(BackupError::SizeEstimate)?;
+                estimated_size = Some(size);
+                save(&save_data, estimated_size)
+                    .await
+                    .map_err(BackupError::Save)?;
+                size
+            }
+        };
+        if let Some(max_size) = max_size
+            && size > max_size
+        {
+            return Err(BackupError::TooBig {
+                estimated_size: size,
+                max_size,
+            });
+        }
+        // A file left over from an interrupted send will get truncated, so its space counts as available
+        let existing_file_size = metadata(file_path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        let available_space = statvfs(file_path.parent().unwrap_or(file_path))
+            .map_err(BackupError::Statvfs)?
+            .available_space()
+            + existing_file_size;
+        if size > available_space {
+            return Err(BackupError::NotEnoughSpace {
+                estimated_size: size,
+                available_space,
+            });
+        }
         let file = OpenOptions::new()
             .create(true)
             .write(true)
@@ -67,7 +165,9 @@ pub async fn backup<ReserveError, MarkUsedError, SaveError>(
             .await
             .map_err(BackupError::Send)?;
         save_data = BackupSaveData::Uploading(Default::default());
-        save(&save_data).await.map_err(BackupError::Save)?;
+        save(&save_data, estimated_size)
+            .await
+            .map_err(BackupError::Save)?;
     }
     if let BackupSaveData::Uploading(upload_save_data) = &save_data {
         upload_chunked_2(
@@ -80,19 +180,31 @@ pub async fn backup<ReserveError, MarkUsedError, SaveError>(
             operation_scheduler,
             &mut async |upload_save_data| {
                 save_data = BackupSaveData::Uploading(upload_save_data.clone());
-                save(&save_data).await?;
+                save(&save_data, estimated_size).await?;
                 Ok(())
             },
         )
         .await
         .map_err(BackupError::Upload)?;
         save_data = BackupSaveData::RemovingFile;
-        save(&save_data).await.map_err(BackupError::Save)?;
+        save(&save_data, estimated_size)
+            .await
+            .map_err(BackupError::Save)?;
     }
+    let mut size = None;
     if let BackupSaveData::RemovingFile = save_data {
+        match zfs_release(&snapshot_name).await {
+            Ok(()) => {}
+            Err(e) if e.is_permission_denied() => on_warning(BackupWarning::ReleaseDenied(&e)),
+            Err(e) => return Err(BackupError::Release(e)),
+        }
+        size = metadata(file_path)
+            .await
+            .ok()
+            .map(|metadata| metadata.len());
         remove_file(&file_path)
             .await
             .map_err(BackupError::RemoveFile)?;
     }
-    Ok(())
+    Ok(size)
 }
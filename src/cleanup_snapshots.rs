@@ -0,0 +1,51 @@
+use crate::{
+    run::{AutoBackupState, parse_snapshot_number},
+    save_data::AutoBackupConfig,
+    zfs_commands::{
+        ZfsCommandError, zfs_dataset_name, zfs_destroy_snapshot, zfs_held_snapshots, zfs_release,
+    },
+};
+
+/// What [`cleanup_snapshots`] did
+#[derive(Debug, Default)]
+pub struct CleanupSnapshotsResult {
+    /// Snapshots that were uploaded, but still had a hold because the program stopped before releasing it.
+    /// The hold was released, but the snapshot was kept.
+    pub released: Vec<String>,
+    /// Snapshots that were held but never uploaded, and are not the snapshot currently being backed up
+    pub destroyed: Vec<String>,
+}
+
+/// Finds snapshots that still have a hold from being backed up.
+/// Snapshots that were already uploaded are released, and snapshots that will never be uploaded are destroyed.
+/// The snapshot of a backup that is in progress is not changed.
+pub async fn cleanup_snapshots(
+    AutoBackupConfig {
+        dataset,
+        snapshot_prefix,
+        ..
+    }: &AutoBackupConfig<'_>,
+    AutoBackupState {
+        snapshots_backed_up,
+        backing_up_progress,
+        ..
+    }: &AutoBackupState,
+) -> Result<CleanupSnapshotsResult, ZfsCommandError> {
+    let dataset_name = zfs_dataset_name(dataset);
+    let snapshot_name_prefix = format!("{dataset_name}@{snapshot_prefix}");
+    let mut result = CleanupSnapshotsResult::default();
+    for snapshot in zfs_held_snapshots(&dataset_name).await? {
+        let Some(snapshot_number) = parse_snapshot_number(&snapshot, &snapshot_name_prefix) else {
+            continue;
+        };
+        if snapshot_number < *snapshots_backed_up {
+            zfs_release(&snapshot).await?;
+            result.released.push(snapshot);
+        } else if snapshot_number > *snapshots_backed_up || backing_up_progress.is_none() {
+            zfs_release(&snapshot).await?;
+            zfs_destroy_snapshot(&snapshot).await?;
+            result.destroyed.push(snapshot);
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,31 @@
+use clap::Parser;
+use tokio::fs::read_to_string;
+use zfs_incremental_backup::{
+    cleanup_snapshots::{CleanupSnapshotsResult, cleanup_snapshots},
+    lock_save_data::lock_save_data,
+    save_data::parse_save_data,
+};
+
+/// Releases the holds on snapshots that were already uploaded, and destroys held snapshots that will never be uploaded.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[arg(long)]
+    save_data_path: String,
+}
+
+pub async fn cleanup_snapshots_cli(Cli { save_data_path }: Cli) {
+    let _lock = lock_save_data(&save_data_path).unwrap();
+    let file_data = parse_save_data(&read_to_string(&save_data_path).await.unwrap()).unwrap();
+    let CleanupSnapshotsResult {
+        released,
+        destroyed,
+    } = cleanup_snapshots(&file_data.config, &file_data.state)
+        .await
+        .unwrap();
+    for snapshot in &released {
+        println!("Released {snapshot}");
+    }
+    for snapshot in &destroyed {
+        println!("Destroyed {snapshot}");
+    }
+}
@@ -0,0 +1,20 @@
+use std::io::stdout;
+
+use clap::{CommandFactory, Parser};
+use clap_complete::{Shell, generate};
+
+/// Prints a shell completion script.
+/// For example, `zfs-incremental-backup completions bash > /usr/share/bash-completion/completions/zfs-incremental-backup`.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    shell: Shell,
+}
+
+pub async fn completions_cli(Cli { shell }: Cli) {
+    generate(
+        shell,
+        &mut crate::Cli::command(),
+        env!("CARGO_PKG_NAME"),
+        &mut stdout(),
+    );
+}
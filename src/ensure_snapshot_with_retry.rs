@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use tokio::time::sleep;
+use zfs_wrapper::{ZfsEnsureSnapshotError, ZfsSnapshot, zfs_ensure_snapshot};
+
+/// How to retry taking a snapshot if it fails because the pool is busy
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotRetryOptions {
+    /// The maximum number of retries after the first attempt
+    pub max_retries: u32,
+    /// The delay before the first retry. The delay doubles after every retry.
+    pub initial_delay: Duration,
+}
+
+/// Errors from `zfs snapshot` that will not go away by waiting
+const PERMANENT_ERRORS: &[&str] = &[
+    "permission denied",
+    "dataset does not exist",
+    "no such pool",
+    "invalid character",
+    "out of space",
+];
+
+fn is_permanent(error: &ZfsEnsureSnapshotError) -> bool {
+    let message = format!("{error:?}").to_lowercase();
+    PERMANENT_ERRORS
+        .iter()
+        .any(|permanent_error| message.contains(permanent_error))
+}
+
+/// Same as [`zfs_ensure_snapshot`], but retries with exponential backoff if the snapshot could not be taken for a reason that could be transient (such as the pool being busy).
+/// `on_retry` is called with the error, the delay before retrying, and the retry number before every retry.
+pub async fn zfs_ensure_snapshot_with_retry(
+    zfs_snapshot: ZfsSnapshot<'_>,
+    SnapshotRetryOptions {
+        max_retries,
+        initial_delay,
+    }: SnapshotRetryOptions,
+    on_retry: &mut impl FnMut(&ZfsEnsureSnapshotError, Duration, u32),
+) -> Result<(), ZfsEnsureSnapshotError> {
+    let mut delay = initial_delay;
+    let mut retries = 0;
+    loop {
+        match zfs_ensure_snapshot(zfs_snapshot.clone()).await {
+            Ok(_) => break Ok(()),
+            Err(e) if retries < max_retries && !is_permanent(&e) => {
+                retries += 1;
+                on_retry(&e, delay, retries);
+                sleep(delay).await;
+                delay *= 2;
+            }
+            Err(e) => break Err(e),
+        }
+    }
+}
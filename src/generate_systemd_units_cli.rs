@@ -0,0 +1,126 @@
+use std::{env::current_exe, iter, path::PathBuf};
+
+use clap::Parser;
+use tokio::fs::write;
+
+use crate::run_cli;
+
+/// Creates a systemd service which runs `zfs-incremental-backup run`, and a timer which starts the service on a schedule.
+///
+/// Example:
+/// zfs-incremental-backup generate-systemd-units --name backup-immich -- --save-data-path /mnt/para-z/backups/immich/save_data.ron --temp-dir /mnt/para-z/backups/immich/temp --storage-class DEEP_ARCHIVE --chunk-size 5000000000
+#[derive(Debug, Parser)]
+pub struct Cli {
+    /// The units will be called `{name}.service` and `{name}.timer`
+    #[arg(long)]
+    name: String,
+    /// When to run backups, in the format of systemd's `OnCalendar=`
+    #[arg(long, default_value = "daily")]
+    on_calendar: String,
+    /// The directory to write the unit files to
+    #[arg(long, default_value = "/etc/systemd/system")]
+    output_dir: PathBuf,
+    /// The arguments for `run`, after a `--`
+    #[arg(last = true, required = true)]
+    run_args: Vec<String>,
+}
+
+/// Quotes an argument so that systemd's `ExecStart=` will pass it to the program unchanged
+fn systemd_escape(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    if escaped.is_empty() || escaped.contains(char::is_whitespace) || escaped != arg {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+pub async fn generate_systemd_units_cli(
+    Cli {
+        name,
+        on_calendar,
+        output_dir,
+        run_args,
+    }: Cli,
+) {
+    // Catch mistakes in the arguments now instead of when the service runs
+    if let Err(e) =
+        run_cli::Cli::try_parse_from(iter::once("run".to_owned()).chain(run_args.iter().cloned()))
+    {
+        e.exit();
+    }
+    let exec_start = iter::once(current_exe().unwrap().to_string_lossy().into_owned())
+        .chain(iter::once("run".to_owned()))
+        .chain(run_args)
+        .map(|arg| systemd_escape(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let service = format!(
+        "[Unit]
+Description=ZFS incremental backup ({name})
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=oneshot
+NotifyAccess=main
+ExecStart={exec_start}
+"
+    );
+    let timer = format!(
+        "[Unit]
+Description=Start {name}.service on a schedule
+
+[Timer]
+OnCalendar={on_calendar}
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+"
+    );
+    let service_path = output_dir.join(format!("{name}.service"));
+    let timer_path = output_dir.join(format!("{name}.timer"));
+    write(&service_path, service).await.unwrap();
+    write(&timer_path, timer).await.unwrap();
+    println!("Created {service_path:?} and {timer_path:?}");
+    println!("Run `systemctl daemon-reload && systemctl enable --now {name}.timer` to enable it");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn systemd_escape_leaves_plain_arguments_alone() {
+        assert_eq!(systemd_escape("--chunk-size"), "--chunk-size");
+        assert_eq!(
+            systemd_escape("/mnt/pool/save_data.ron"),
+            "/mnt/pool/save_data.ron"
+        );
+    }
+
+    #[test]
+    fn systemd_escape_quotes_whitespace_and_empty_arguments() {
+        assert_eq!(systemd_escape("a b"), "\"a b\"");
+        assert_eq!(systemd_escape("a\tb"), "\"a\tb\"");
+        assert_eq!(systemd_escape(""), "\"\"");
+    }
+
+    #[test]
+    fn systemd_escape_escapes_specifiers_and_variables() {
+        assert_eq!(systemd_escape("100%"), "\"100%%\"");
+        assert_eq!(systemd_escape("$HOME"), "\"$$HOME\"");
+    }
+
+    #[test]
+    fn systemd_escape_escapes_quotes_and_backslashes() {
+        assert_eq!(systemd_escape("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(systemd_escape("a\\b"), "\"a\\\\b\"");
+        assert_eq!(systemd_escape("echo 'done'"), "\"echo 'done'\"");
+    }
+}
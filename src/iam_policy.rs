@@ -0,0 +1,36 @@
+use serde_json::{Value, json};
+
+/// Creates an IAM policy with only the permissions that this program needs for backups under `object_prefix` in `bucket`.
+/// If `restore` is true, the policy also allows downloading and restoring archived backups.
+/// If `delete` is true, the policy also allows deleting objects, which `audit --delete-orphans` and `abort` need.
+pub fn iam_policy(bucket: &str, object_prefix: &str, restore: bool, delete: bool) -> Value {
+    let mut object_actions = vec!["s3:PutObject", "s3:GetObject"];
+    if restore {
+        object_actions.push("s3:RestoreObject");
+    }
+    if delete {
+        object_actions.push("s3:DeleteObject");
+    }
+    json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Sid": "ListBackups",
+                "Effect": "Allow",
+                "Action": "s3:ListBucket",
+                "Resource": format!("arn:aws:s3:::{bucket}"),
+                "Condition": {
+                    "StringLike": {
+                        "s3:prefix": format!("{object_prefix}*"),
+                    },
+                },
+            },
+            {
+                "Sid": "ReadWriteBackups",
+                "Effect": "Allow",
+                "Action": object_actions,
+                "Resource": format!("arn:aws:s3:::{bucket}/{object_prefix}*"),
+            },
+        ],
+    })
+}
@@ -1,25 +1,11 @@
 use clap::Parser;
-use serde::{Deserialize, Serialize};
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use zfs_incremental_backup::{
+    save_data::{AutoBackupConfig, AutoBackupFileData, SAVE_DATA_VERSION},
+    write_save_data::create_save_data,
+};
 use zfs_wrapper::ZfsDataset;
 
-use crate::run::AutoBackupState;
-
-/// Configuration that should not change for the lifetime of this file, unless you change the zpool / dataset name
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AutoBackupConfig<'a> {
-    pub dataset: ZfsDataset<'a>,
-    pub bucket: String,
-    pub snapshot_prefix: String,
-    pub object_prefix: String,
-}
-
-/// The config and state are in the same file so that the user doesn't accidentally specify the wrong config and state
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AutoBackupFileData<'a> {
-    pub config: AutoBackupConfig<'a>,
-    pub state: AutoBackupState,
-}
+use crate::parse_snapshot_prefix::parse_snapshot_prefix;
 
 #[derive(Debug, Parser)]
 pub struct Cli {
@@ -30,7 +16,8 @@ pub struct Cli {
     /// For example, if this is `backup`, then snapshots will be called `backup0`, `backup1`, etc.
     /// Incremental backups will be separated by `_`, so they will be called `backup0_backup1`, `backup1_backup2`, etc.
     /// `backup0_backup1` means that the "file" contains the data to create @backup1 if you already have @backup0
-    #[arg(long)]
+    /// It can't end with a digit, so that snapshots with different prefixes (when backing up a dataset to multiple places) can't be confused.
+    #[arg(long, value_parser = parse_snapshot_prefix)]
     snapshot_prefix: String,
     /// The S3 bucket to upload to
     #[arg(long)]
@@ -53,31 +40,26 @@ pub async fn init_cli(
         save_data_path,
     }: Cli,
 ) {
-    OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open(save_data_path)
-        .await
-        .unwrap()
-        .write_all(
-            ron::ser::to_string_pretty(
-                &AutoBackupFileData {
-                    config: AutoBackupConfig {
-                        dataset: ZfsDataset {
-                            zpool: zpool.into(),
-                            dataset: dataset.into(),
-                        },
-                        snapshot_prefix,
-                        object_prefix,
-                        bucket,
+    create_save_data(
+        &save_data_path,
+        &ron::ser::to_string_pretty(
+            &AutoBackupFileData {
+                version: SAVE_DATA_VERSION,
+                config: AutoBackupConfig {
+                    dataset: ZfsDataset {
+                        zpool: zpool.into(),
+                        dataset: dataset.into(),
                     },
-                    state: Default::default(),
+                    snapshot_prefix,
+                    object_prefix,
+                    bucket,
                 },
-                Default::default(),
-            )
-            .unwrap()
-            .as_bytes(),
+                state: Default::default(),
+            },
+            Default::default(),
         )
-        .await
-        .unwrap();
+        .unwrap(),
+    )
+    .await
+    .unwrap();
 }
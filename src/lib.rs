@@ -0,0 +1,28 @@
+//! Incrementally backs up ZFS datasets to S3 by uploading the output of `zfs send -w` in chunks.
+//! The `zfs-incremental-backup` binary is a CLI for this library, but the building blocks can be used to write other programs, such as a GUI or server.
+//!
+//! [`run::run`] takes a snapshot and backs it up, saving its progress so that it can continue after being interrupted.
+//! [`receive::receive`] downloads backups and restores them with `zfs receive`.
+
+pub mod abort;
+pub mod archive_restore;
+pub mod audit;
+pub mod backup;
+pub mod cleanup_snapshots;
+pub mod ensure_snapshot_with_retry;
+pub mod iam_policy;
+pub mod list_objects;
+pub mod lock_save_data;
+pub mod metrics_textfile;
+pub mod ping_healthcheck;
+pub mod prune_snapshots;
+pub mod receive;
+pub mod remote_backups;
+pub mod run;
+pub mod run_hook;
+pub mod save_data;
+pub mod sd_notify;
+pub mod validate;
+pub mod write_save_data;
+pub mod zfs_commands;
+pub mod zfs_send_size_estimate;
@@ -0,0 +1,45 @@
+use aws_sdk_s3::{error::SdkError, operation::list_objects_v2::ListObjectsV2Error, types::Object};
+
+/// Lists all of the S3 objects whose keys start with `prefix`
+pub async fn list_objects(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    prefix: &str,
+) -> Result<Vec<Object>, SdkError<ListObjectsV2Error>> {
+    let mut objects = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let output = client
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(prefix)
+            .set_continuation_token(continuation_token)
+            .send()
+            .await?;
+        objects.extend_from_slice(output.contents());
+        continuation_token = output.next_continuation_token().map(ToOwned::to_owned);
+        if continuation_token.is_none() {
+            break Ok(objects);
+        }
+    }
+}
+
+/// Lists the S3 objects (`{object_key}/0`, `{object_key}/1`, ...) that an uploaded file was split into, sorted by their index.
+/// Concatenating the chunks in this order results in the original file.
+pub async fn list_chunks(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    object_key: &str,
+) -> Result<Vec<Object>, SdkError<ListObjectsV2Error>> {
+    let prefix = format!("{object_key}/");
+    let mut chunks = list_objects(client, bucket, &prefix)
+        .await?
+        .into_iter()
+        .filter_map(|object| {
+            let index = object.key()?.strip_prefix(&prefix)?.parse::<usize>().ok()?;
+            Some((index, object))
+        })
+        .collect::<Vec<_>>();
+    chunks.sort_by_key(|(index, _)| *index);
+    Ok(chunks.into_iter().map(|(_, object)| object).collect())
+}
@@ -0,0 +1,90 @@
+use clap::Parser;
+use zfs_incremental_backup::{
+    remote_backups::{RemoteBackups, list_remote_backups},
+    run::backup_object_name,
+};
+
+use crate::{
+    remote_backup_args::RemoteBackupArgs,
+    s3_client::{S3ClientArgs, create_s3_client},
+};
+
+/// Lists the backups on S3, including how many chunks they have, their size, when they were uploaded, and their storage class.
+/// Doesn't need the save data file or the dataset to exist, so it can be used on a new machine before restoring.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[command(flatten)]
+    remote_backup_args: RemoteBackupArgs,
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+}
+
+pub async fn list_snapshots_cli(
+    Cli {
+        remote_backup_args:
+            RemoteBackupArgs {
+                bucket,
+                object_prefix,
+                snapshot_prefix,
+            },
+        s3_client_args,
+    }: Cli,
+) {
+    let client = create_s3_client(s3_client_args).await;
+    let RemoteBackups {
+        backups,
+        missing_backups,
+        other_objects,
+    } = list_remote_backups(&client, &bucket, &object_prefix, &snapshot_prefix)
+        .await
+        .unwrap();
+    println!("snapshot\tobject\tchunks\tbytes\tuploaded\tstorage class");
+    // Every missing backup is before the last backup, so they are printed in order between the backups
+    let mut missing_backups = missing_backups.into_iter().peekable();
+    for backup in &backups {
+        while let Some(snapshot_number) =
+            missing_backups.next_if(|snapshot_number| *snapshot_number < backup.snapshot_number)
+        {
+            println!(
+                "{snapshot_prefix}{snapshot_number}\t{}\tmissing",
+                backup_object_name(&snapshot_prefix, snapshot_number)
+            );
+        }
+        let size = backup
+            .chunks
+            .iter()
+            .map(|chunk| chunk.size().unwrap_or_default())
+            .sum::<i64>();
+        let uploaded = backup
+            .chunks
+            .iter()
+            .filter_map(|chunk| chunk.last_modified())
+            .max()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let mut storage_classes = backup
+            .chunks
+            .iter()
+            .filter_map(|chunk| chunk.storage_class())
+            .map(|storage_class| storage_class.as_str())
+            .collect::<Vec<_>>();
+        storage_classes.sort();
+        storage_classes.dedup();
+        println!(
+            "{snapshot_prefix}{}\t{}\t{}\t{size}\t{uploaded}\t{}",
+            backup.snapshot_number,
+            backup.object_name,
+            backup.chunks.len(),
+            storage_classes.join(",")
+        );
+    }
+    if backups.is_empty() {
+        println!("No backups found");
+    }
+    if !other_objects.is_empty() {
+        println!(
+            "{} other objects under the prefix are not part of the backups",
+            other_objects.len()
+        );
+    }
+}
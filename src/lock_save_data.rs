@@ -0,0 +1,29 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io,
+};
+
+#[derive(Debug)]
+pub enum LockSaveDataError {
+    Open(io::Error),
+    /// Another instance of this program is using the same save data file
+    Locked,
+    Lock(io::Error),
+}
+
+/// Takes an exclusive lock on `{save_data_path}.lock`, so that multiple instances of this program can't use the same save data file at the same time.
+/// A separate file is locked so that the save data file itself can be replaced.
+/// The lock is released when the returned file is dropped, or if the program exits or crashes.
+pub fn lock_save_data(save_data_path: &str) -> Result<File, LockSaveDataError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(format!("{save_data_path}.lock"))
+        .map_err(LockSaveDataError::Open)?;
+    file.try_lock().map_err(|e| match e {
+        TryLockError::WouldBlock => LockSaveDataError::Locked,
+        TryLockError::Error(e) => LockSaveDataError::Lock(e),
+    })?;
+    Ok(file)
+}
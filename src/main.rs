@@ -1,8 +1,23 @@
-mod backup;
+mod abort_cli;
+mod audit_cli;
+mod cleanup_snapshots_cli;
+mod completions_cli;
+mod generate_systemd_units_cli;
 mod init_cli;
+mod list_snapshots_cli;
+mod man_cli;
+mod parse_metrics_textfile;
+mod parse_snapshot_prefix;
 mod parse_storage_class;
-mod run;
+mod parse_tier;
+mod print_iam_policy_cli;
+mod receive_cli;
+mod remote_backup_args;
+mod restore_request_cli;
+mod restore_wait_cli;
 mod run_cli;
+mod s3_client;
+mod validate_cli;
 
 use clap::{Parser, Subcommand};
 
@@ -16,6 +31,18 @@ struct Cli {
 enum Commands {
     Init(init_cli::Cli),
     Run(run_cli::Cli),
+    RestoreRequest(restore_request_cli::Cli),
+    RestoreWait(restore_wait_cli::Cli),
+    GenerateSystemdUnits(generate_systemd_units_cli::Cli),
+    ListSnapshots(list_snapshots_cli::Cli),
+    Receive(receive_cli::Cli),
+    Validate(validate_cli::Cli),
+    PrintIamPolicy(print_iam_policy_cli::Cli),
+    Audit(audit_cli::Cli),
+    CleanupSnapshots(cleanup_snapshots_cli::Cli),
+    Completions(completions_cli::Cli),
+    Man(man_cli::Cli),
+    Abort(abort_cli::Cli),
 }
 
 #[tokio::main]
@@ -24,5 +51,25 @@ async fn main() {
     match command {
         Commands::Init(command) => init_cli::init_cli(command).await,
         Commands::Run(command) => run_cli::run_cli(command).await,
+        Commands::RestoreRequest(command) => {
+            restore_request_cli::restore_request_cli(command).await
+        }
+        Commands::RestoreWait(command) => restore_wait_cli::restore_wait_cli(command).await,
+        Commands::GenerateSystemdUnits(command) => {
+            generate_systemd_units_cli::generate_systemd_units_cli(command).await
+        }
+        Commands::ListSnapshots(command) => list_snapshots_cli::list_snapshots_cli(command).await,
+        Commands::Receive(command) => receive_cli::receive_cli(command).await,
+        Commands::Validate(command) => validate_cli::validate_cli(command).await,
+        Commands::PrintIamPolicy(command) => {
+            print_iam_policy_cli::print_iam_policy_cli(command).await
+        }
+        Commands::Audit(command) => audit_cli::audit_cli(command).await,
+        Commands::CleanupSnapshots(command) => {
+            cleanup_snapshots_cli::cleanup_snapshots_cli(command).await
+        }
+        Commands::Completions(command) => completions_cli::completions_cli(command).await,
+        Commands::Man(command) => man_cli::man_cli(command).await,
+        Commands::Abort(command) => abort_cli::abort_cli(command).await,
     }
 }
@@ -0,0 +1,18 @@
+use std::path::PathBuf;
+
+use clap::{CommandFactory, Parser};
+
+/// Writes man pages for every command to a directory, such as `/usr/local/share/man/man1`
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[arg(long)]
+    output_dir: PathBuf,
+}
+
+pub async fn man_cli(Cli { output_dir }: Cli) {
+    clap_mangen::generate_to(
+        crate::Cli::command().name(env!("CARGO_PKG_NAME")),
+        output_dir,
+    )
+    .unwrap();
+}
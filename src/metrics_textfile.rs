@@ -0,0 +1,317 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::fs::{read_to_string, rename, write};
+
+const METRIC_PREFIX: &str = "zfs_incremental_backup_";
+
+/// Information about a finished `run`, successful or not
+#[derive(Debug)]
+pub struct RunMetrics<'a> {
+    /// `zpool/dataset`, used as a label so that multiple datasets can be told apart.
+    /// `None` if the save data couldn't be read, in which case the labels from the previous metrics are kept.
+    pub dataset: Option<&'a str>,
+    pub success: bool,
+    pub start_time: SystemTime,
+    pub duration: Duration,
+    /// `None` if the save data couldn't be read, in which case the previous value is kept
+    pub snapshots_backed_up: Option<usize>,
+    /// The size of the `zfs send` output that was uploaded, in bytes.
+    /// `None` if no backup was finished, in which case the previous value is kept.
+    pub backup_bytes: Option<u64>,
+    /// The number of chunks that the backup was uploaded as
+    pub backup_objects: Option<u64>,
+}
+
+/// Escapes a Prometheus label value
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// The labels and values from a metrics file written by [`write_metrics_textfile`]
+#[derive(Debug, Default)]
+struct PreviousMetrics {
+    labels: Option<String>,
+    values: HashMap<String, f64>,
+}
+
+fn parse_previous_metrics(contents: &str) -> PreviousMetrics {
+    let mut previous_metrics = PreviousMetrics::default();
+    for line in contents.lines().filter(|line| !line.starts_with('#')) {
+        let Some((name_and_labels, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let Some(name_and_labels) = name_and_labels.strip_prefix(METRIC_PREFIX) else {
+            continue;
+        };
+        let (name, labels) = match name_and_labels.find('{') {
+            Some(labels_start) => name_and_labels.split_at(labels_start),
+            None => (name_and_labels, ""),
+        };
+        if let Ok(value) = value.parse() {
+            previous_metrics.labels = Some(labels.to_owned());
+            previous_metrics.values.insert(name.to_owned(), value);
+        }
+    }
+    previous_metrics
+}
+
+/// Creates the contents of the metrics file.
+/// The last success time and the failure counts are carried forward from the previous metrics, since each run only knows about itself.
+fn metrics_contents(
+    RunMetrics {
+        dataset,
+        success,
+        start_time,
+        duration,
+        snapshots_backed_up,
+        backup_bytes,
+        backup_objects,
+    }: RunMetrics<'_>,
+    previous_metrics: &PreviousMetrics,
+) -> String {
+    let previous = |name: &str| previous_metrics.values.get(name).copied();
+    let labels = match dataset {
+        Some(dataset) => format!(r#"{{dataset="{}"}}"#, escape_label_value(dataset)),
+        None => previous_metrics.labels.clone().unwrap_or_default(),
+    };
+    let start_timestamp = start_time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let metrics = [
+        (
+            "last_run_success",
+            "gauge",
+            "Whether the last run finished successfully",
+            Some(if success { 1.0 } else { 0.0 }),
+        ),
+        (
+            "last_run_start_timestamp_seconds",
+            "gauge",
+            "When the last run started, in seconds since the Unix epoch",
+            Some(start_timestamp),
+        ),
+        (
+            "last_run_duration_seconds",
+            "gauge",
+            "How long the last run took",
+            Some(duration.as_secs_f64()),
+        ),
+        (
+            "last_success_timestamp_seconds",
+            "gauge",
+            "When the last successful run finished, in seconds since the Unix epoch",
+            if success {
+                Some(start_timestamp + duration.as_secs_f64())
+            } else {
+                previous("last_success_timestamp_seconds")
+            },
+        ),
+        (
+            "consecutive_failures",
+            "gauge",
+            "The number of runs that failed since the last successful run",
+            Some(if success {
+                0.0
+            } else {
+                previous("consecutive_failures").unwrap_or_default() + 1.0
+            }),
+        ),
+        (
+            "failures_total",
+            "counter",
+            "The number of runs that failed",
+            Some(previous("failures_total").unwrap_or_default() + if success { 0.0 } else { 1.0 }),
+        ),
+        (
+            "snapshots_backed_up",
+            "gauge",
+            "The number of snapshots that are completely uploaded",
+            snapshots_backed_up
+                .map(|snapshots_backed_up| snapshots_backed_up as f64)
+                .or_else(|| previous("snapshots_backed_up")),
+        ),
+        (
+            "last_backup_bytes",
+            "gauge",
+            "The size of the last finished backup, in bytes",
+            backup_bytes
+                .map(|bytes| bytes as f64)
+                .or_else(|| previous("last_backup_bytes")),
+        ),
+        (
+            "last_backup_objects",
+            "gauge",
+            "The number of objects that the last finished backup was uploaded as",
+            backup_objects
+                .map(|objects| objects as f64)
+                .or_else(|| previous("last_backup_objects")),
+        ),
+        (
+            "uploaded_bytes_total",
+            "counter",
+            "The total size of the finished backups, in bytes",
+            Some(
+                previous("uploaded_bytes_total").unwrap_or_default()
+                    + backup_bytes.unwrap_or_default() as f64,
+            ),
+        ),
+        (
+            "uploaded_objects_total",
+            "counter",
+            "The total number of objects that the finished backups were uploaded as",
+            Some(
+                previous("uploaded_objects_total").unwrap_or_default()
+                    + backup_objects.unwrap_or_default() as f64,
+            ),
+        ),
+    ];
+    metrics
+        .into_iter()
+        .filter_map(|(name, metric_type, help, value)| {
+            Some(format!(
+                "# HELP {METRIC_PREFIX}{name} {help}\n# TYPE {METRIC_PREFIX}{name} {metric_type}\n{METRIC_PREFIX}{name}{labels} {}\n",
+                value?
+            ))
+        })
+        .collect()
+}
+
+/// Writes metrics in the Prometheus text format, for node_exporter's textfile collector.
+/// The file is written to a temporary file first and then renamed, so that node_exporter never reads a partially written file.
+pub async fn write_metrics_textfile(path: &Path, run_metrics: RunMetrics<'_>) -> io::Result<()> {
+    let previous_metrics = match read_to_string(path).await {
+        Ok(contents) => parse_previous_metrics(&contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => PreviousMetrics::default(),
+        Err(e) => return Err(e),
+    };
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    write(&temp_path, metrics_contents(run_metrics, &previous_metrics)).await?;
+    rename(&temp_path, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_metrics(dataset: Option<&str>, success: bool) -> RunMetrics<'_> {
+        RunMetrics {
+            dataset,
+            success,
+            start_time: UNIX_EPOCH + Duration::from_secs(100),
+            duration: Duration::from_secs(5),
+            snapshots_backed_up: dataset.map(|_| 3),
+            backup_bytes: success.then_some(150),
+            backup_objects: success.then_some(2),
+        }
+    }
+
+    #[test]
+    fn failures_keep_the_last_success() {
+        let success = metrics_contents(
+            run_metrics(Some("pool/dataset"), true),
+            &PreviousMetrics::default(),
+        );
+        assert!(success.contains(
+            "zfs_incremental_backup_last_success_timestamp_seconds{dataset=\"pool/dataset\"} 105\n"
+        ));
+        assert!(
+            success.contains("zfs_incremental_backup_failures_total{dataset=\"pool/dataset\"} 0\n")
+        );
+
+        // The save data couldn't be read, so the dataset and snapshot count are unknown
+        let failure = metrics_contents(run_metrics(None, false), &parse_previous_metrics(&success));
+        assert!(failure.contains(
+            "zfs_incremental_backup_last_success_timestamp_seconds{dataset=\"pool/dataset\"} 105\n"
+        ));
+        assert!(
+            failure.contains(
+                "zfs_incremental_backup_snapshots_backed_up{dataset=\"pool/dataset\"} 3\n"
+            )
+        );
+        assert!(
+            failure
+                .contains("zfs_incremental_backup_last_run_success{dataset=\"pool/dataset\"} 0\n")
+        );
+
+        let failure = metrics_contents(
+            run_metrics(Some("pool/dataset"), false),
+            &parse_previous_metrics(&failure),
+        );
+        assert!(
+            failure.contains(
+                "zfs_incremental_backup_consecutive_failures{dataset=\"pool/dataset\"} 2\n"
+            )
+        );
+        assert!(
+            failure.contains("zfs_incremental_backup_failures_total{dataset=\"pool/dataset\"} 2\n")
+        );
+
+        let success = metrics_contents(
+            run_metrics(Some("pool/dataset"), true),
+            &parse_previous_metrics(&failure),
+        );
+        assert!(
+            success.contains(
+                "zfs_incremental_backup_consecutive_failures{dataset=\"pool/dataset\"} 0\n"
+            )
+        );
+        assert!(
+            success.contains("zfs_incremental_backup_failures_total{dataset=\"pool/dataset\"} 2\n")
+        );
+    }
+
+    #[test]
+    fn upload_counters_add_up_finished_backups() {
+        let success = metrics_contents(
+            run_metrics(Some("pool/dataset"), true),
+            &PreviousMetrics::default(),
+        );
+        let failure = metrics_contents(
+            run_metrics(Some("pool/dataset"), false),
+            &parse_previous_metrics(&success),
+        );
+        assert!(
+            failure.contains(
+                "zfs_incremental_backup_last_backup_bytes{dataset=\"pool/dataset\"} 150\n"
+            )
+        );
+        assert!(failure.contains(
+            "zfs_incremental_backup_uploaded_bytes_total{dataset=\"pool/dataset\"} 150\n"
+        ));
+        let success = metrics_contents(
+            run_metrics(Some("pool/dataset"), true),
+            &parse_previous_metrics(&failure),
+        );
+        assert!(
+            success.contains(
+                "zfs_incremental_backup_last_backup_objects{dataset=\"pool/dataset\"} 2\n"
+            )
+        );
+        assert!(success.contains(
+            "zfs_incremental_backup_uploaded_bytes_total{dataset=\"pool/dataset\"} 300\n"
+        ));
+        assert!(success.contains(
+            "zfs_incremental_backup_uploaded_objects_total{dataset=\"pool/dataset\"} 4\n"
+        ));
+    }
+
+    #[test]
+    fn no_previous_success() {
+        let failure = metrics_contents(run_metrics(None, false), &PreviousMetrics::default());
+        assert!(!failure.contains("last_success_timestamp_seconds"));
+        assert!(!failure.contains("snapshots_backed_up"));
+        assert!(!failure.contains("last_backup_bytes"));
+        assert!(failure.contains("zfs_incremental_backup_uploaded_bytes_total 0\n"));
+        assert!(failure.contains("zfs_incremental_backup_failures_total 1\n"));
+    }
+}
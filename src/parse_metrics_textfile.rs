@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+/// node_exporter's textfile collector only reads files ending with `.prom`
+pub fn parse_metrics_textfile(metrics_textfile: &str) -> Result<PathBuf, String> {
+    if metrics_textfile.ends_with(".prom") {
+        Ok(PathBuf::from(metrics_textfile))
+    } else {
+        Err("must end with `.prom`".to_owned())
+    }
+}
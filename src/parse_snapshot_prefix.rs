@@ -0,0 +1,9 @@
+use zfs_incremental_backup::run::is_valid_snapshot_prefix;
+
+pub fn parse_snapshot_prefix(snapshot_prefix: &str) -> Result<String, String> {
+    if is_valid_snapshot_prefix(snapshot_prefix) {
+        Ok(snapshot_prefix.to_owned())
+    } else {
+        Err("must not be empty or end with a digit".to_owned())
+    }
+}
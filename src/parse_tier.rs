@@ -0,0 +1,5 @@
+use aws_sdk_s3::types::Tier;
+
+pub fn parse_tier(tier: &str) -> Result<Tier, String> {
+    Tier::try_parse(tier).map_err(|e| e.to_string())
+}
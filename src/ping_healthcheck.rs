@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+/// What to tell a healthchecks.io style monitoring service
+#[derive(Debug)]
+pub enum HealthcheckPing {
+    Start,
+    Success,
+    /// Contains the error, which is attached to the ping so that it can be seen in the monitoring service
+    Fail(String),
+}
+
+/// Pings `{ping_url}/start`, `{ping_url}`, or `{ping_url}/fail`.
+pub async fn ping_healthcheck(ping_url: &str, ping: HealthcheckPing) -> reqwest::Result<()> {
+    let (url, body) = match ping {
+        HealthcheckPing::Start => (format!("{ping_url}/start"), String::new()),
+        HealthcheckPing::Success => (ping_url.to_owned(), String::new()),
+        HealthcheckPing::Fail(error) => (format!("{ping_url}/fail"), error),
+    };
+    reqwest::Client::new()
+        .post(&url)
+        .timeout(Duration::from_secs(10))
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
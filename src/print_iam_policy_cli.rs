@@ -0,0 +1,33 @@
+use clap::Parser;
+use zfs_incremental_backup::iam_policy::iam_policy;
+
+/// Prints an IAM policy with only the permissions needed to back up to the bucket and prefix.
+/// Use it instead of giving the backup user full S3 access.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[arg(long)]
+    bucket: String,
+    #[arg(long)]
+    object_prefix: String,
+    /// Also allow restoring archived objects (`restore-request`), for a user that will restore backups
+    #[arg(long)]
+    restore: bool,
+    /// Also allow deleting objects, which is needed by `audit --delete-orphans` and `abort`
+    #[arg(long)]
+    delete: bool,
+}
+
+pub async fn print_iam_policy_cli(
+    Cli {
+        bucket,
+        object_prefix,
+        restore,
+        delete,
+    }: Cli,
+) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&iam_policy(&bucket, &object_prefix, restore, delete))
+            .unwrap()
+    );
+}
@@ -0,0 +1,32 @@
+use std::num::NonZero;
+
+use zfs_wrapper::ZfsDataset;
+
+use crate::{
+    run::parse_snapshot_number,
+    zfs_commands::{ZfsCommandError, zfs_dataset_name, zfs_destroy_snapshot, zfs_list_snapshots},
+};
+
+/// Destroys the local `{snapshot_prefix}{n}` snapshots which were backed up, except for the last `keep` of them.
+/// The last backed up snapshot is always kept, because the next backup is incremental from it.
+/// Returns the snapshots that were destroyed.
+pub async fn prune_snapshots(
+    dataset: &ZfsDataset<'_>,
+    snapshot_prefix: &str,
+    snapshots_backed_up: usize,
+    keep: NonZero<usize>,
+) -> Result<Vec<String>, ZfsCommandError> {
+    let dataset_name = zfs_dataset_name(dataset);
+    let snapshot_name_prefix = format!("{dataset_name}@{snapshot_prefix}");
+    let mut destroyed = Vec::new();
+    for snapshot in zfs_list_snapshots(&dataset_name).await? {
+        let Some(snapshot_number) = parse_snapshot_number(&snapshot, &snapshot_name_prefix) else {
+            continue;
+        };
+        if snapshot_number + keep.get() < snapshots_backed_up {
+            zfs_destroy_snapshot(&snapshot).await?;
+            destroyed.push(snapshot);
+        }
+    }
+    Ok(destroyed)
+}
@@ -0,0 +1,113 @@
+use std::{
+    io,
+    process::{ExitStatus, Stdio},
+};
+
+use aws_sdk_s3::{
+    error::SdkError,
+    operation::{get_object::GetObjectError, list_objects_v2::ListObjectsV2Error},
+};
+use tokio::{io::copy, process::Command};
+
+use crate::{
+    list_objects::list_chunks,
+    run::{backup_object_name, parse_snapshot_number},
+    zfs_commands::{ZfsCommandError, zfs_exists, zfs_list_snapshots},
+};
+
+#[derive(Debug)]
+pub enum ReceiveError {
+    ListSnapshots(ZfsCommandError),
+    List(SdkError<ListObjectsV2Error>),
+    /// There are no chunks uploaded for this object key
+    NoChunks(String),
+    Spawn(io::Error),
+    /// If the object is in `GLACIER` or `DEEP_ARCHIVE`, it needs to be restored first
+    Download(SdkError<GetObjectError>),
+    Pipe(io::Error),
+    Wait(io::Error),
+    Receive(ExitStatus),
+}
+
+/// Finds the next snapshot number to receive by checking which `{snapshot_prefix}{n}` snapshots the target dataset already has.
+/// This is how an interrupted receive resumes. `zfs receive` is atomic, so a snapshot either was received completely or not at all.
+async fn next_snapshot_number(target: &str, snapshot_prefix: &str) -> Result<usize, ReceiveError> {
+    if !zfs_exists(target)
+        .await
+        .map_err(ReceiveError::ListSnapshots)?
+    {
+        return Ok(0);
+    }
+    let snapshot_prefix = format!("{target}@{snapshot_prefix}");
+    Ok(zfs_list_snapshots(target)
+        .await
+        .map_err(ReceiveError::ListSnapshots)?
+        .iter()
+        .filter_map(|snapshot| parse_snapshot_number(snapshot, &snapshot_prefix))
+        .map(|snapshot_number| snapshot_number + 1)
+        .max()
+        .unwrap_or_default())
+}
+
+/// Downloads the backups needed to create snapshot number `snapshot_number`, and pipes them into `zfs receive` in order.
+/// Backups which the target dataset already has snapshots for are skipped, so this can be interrupted and ran again.
+/// `on_receive` is called with the object key and the number of chunks before each backup is received.
+pub async fn receive(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    object_prefix: &str,
+    snapshot_prefix: &str,
+    snapshot_number: usize,
+    target: &str,
+    on_receive: &mut impl FnMut(&str, usize),
+) -> Result<(), ReceiveError> {
+    for snapshot_number in next_snapshot_number(target, snapshot_prefix).await?..=snapshot_number {
+        let object_key = format!(
+            "{object_prefix}{}",
+            backup_object_name(snapshot_prefix, snapshot_number)
+        );
+        let chunks = list_chunks(client, bucket, &object_key)
+            .await
+            .map_err(ReceiveError::List)?;
+        if chunks.is_empty() {
+            return Err(ReceiveError::NoChunks(object_key));
+        }
+        on_receive(&object_key, chunks.len());
+        let mut child = Command::new("zfs")
+            .args(["receive", target])
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(ReceiveError::Spawn)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let piped = async {
+            for chunk_key in chunks.iter().filter_map(|chunk| chunk.key()) {
+                let output = client
+                    .get_object()
+                    .bucket(bucket)
+                    .key(chunk_key)
+                    .send()
+                    .await
+                    .map_err(ReceiveError::Download)?;
+                copy(&mut output.body.into_async_read(), &mut stdin)
+                    .await
+                    .map_err(ReceiveError::Pipe)?;
+            }
+            Ok::<(), ReceiveError>(())
+        }
+        .await;
+        if let Err(e) = piped {
+            // Otherwise `zfs receive` could receive the partial stream when stdin is closed.
+            // The error from killing it is ignored because it may have already exited, and the download error is the one that matters.
+            let _ = child.kill().await;
+            return Err(e);
+        }
+        // Closing stdin lets `zfs receive` know that the stream ended
+        drop(stdin);
+        let status = child.wait().await.map_err(ReceiveError::Wait)?;
+        if !status.success() {
+            return Err(ReceiveError::Receive(status));
+        }
+    }
+    Ok(())
+}
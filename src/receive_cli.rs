@@ -0,0 +1,53 @@
+use clap::Parser;
+use zfs_incremental_backup::receive::receive;
+
+use crate::{
+    remote_backup_args::RemoteBackupArgs,
+    s3_client::{S3ClientArgs, create_s3_client},
+};
+
+/// Downloads backups and pipes them into `zfs receive`, restoring a snapshot and all of the snapshots before it.
+/// If the objects are in `GLACIER` or `DEEP_ARCHIVE`, use `restore-request` and `restore-wait` first.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[command(flatten)]
+    remote_backup_args: RemoteBackupArgs,
+    /// The number of the snapshot to receive, for example `3` for `backup3`.
+    /// Snapshots that the target dataset already has are skipped, so an interrupted receive can be continued by running this again.
+    #[arg(long)]
+    snapshot: usize,
+    /// The dataset to receive into, such as `zpool/dataset`. It should not exist yet, unless continuing an interrupted receive.
+    #[arg(long)]
+    target: String,
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+}
+
+pub async fn receive_cli(
+    Cli {
+        remote_backup_args:
+            RemoteBackupArgs {
+                bucket,
+                object_prefix,
+                snapshot_prefix,
+            },
+        snapshot,
+        target,
+        s3_client_args,
+    }: Cli,
+) {
+    let client = create_s3_client(s3_client_args).await;
+    receive(
+        &client,
+        &bucket,
+        &object_prefix,
+        &snapshot_prefix,
+        snapshot,
+        &target,
+        &mut |object_key, chunks| {
+            println!("Receiving {object_key} ({chunks} chunks)");
+        },
+    )
+    .await
+    .unwrap();
+}
@@ -0,0 +1,15 @@
+use clap::Args;
+
+/// Where the backups are on S3.
+/// These are the same as what was given to `init`, so that they can be used without the save data file.
+#[derive(Debug, Args)]
+pub struct RemoteBackupArgs {
+    /// The S3 bucket that the backups were uploaded to
+    #[arg(long)]
+    pub bucket: String,
+    /// The prefix that the backups were uploaded to
+    #[arg(long)]
+    pub object_prefix: String,
+    #[arg(long)]
+    pub snapshot_prefix: String,
+}
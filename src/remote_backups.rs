@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use aws_sdk_s3::{error::SdkError, operation::list_objects_v2::ListObjectsV2Error, types::Object};
+
+use crate::{
+    list_objects::list_objects,
+    run::{backup_object_name, parse_backup_object_name},
+};
+
+/// The chunks of one uploaded `zfs send` file
+#[derive(Debug)]
+pub struct RemoteBackup {
+    /// The number of the snapshot that this backup creates
+    pub snapshot_number: usize,
+    /// For example, `backup0_backup1`
+    pub object_name: String,
+    /// Sorted by their index
+    pub chunks: Vec<Object>,
+}
+
+#[derive(Debug)]
+pub struct RemoteBackups {
+    /// The backups that were found, sorted by their snapshot number.
+    /// The last backup could be partially uploaded if a backup is in progress or was interrupted.
+    pub backups: Vec<RemoteBackup>,
+    /// The snapshot numbers before the last backup that was found which have no objects.
+    /// Snapshots after a missing backup can't be received.
+    pub missing_backups: Vec<usize>,
+    /// Objects under the prefix which are not chunks of a backup
+    pub other_objects: Vec<Object>,
+}
+
+/// Finds the backups that were uploaded by looking at the objects in the bucket, so it works without the save data file.
+pub async fn list_remote_backups(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    object_prefix: &str,
+    snapshot_prefix: &str,
+) -> Result<RemoteBackups, SdkError<ListObjectsV2Error>> {
+    Ok(remote_backups(
+        list_objects(client, bucket, object_prefix).await?,
+        object_prefix,
+        snapshot_prefix,
+    ))
+}
+
+/// Every object is sorted by the snapshot number in its name, so a missing backup doesn't hide the later backups
+fn remote_backups(
+    objects: Vec<Object>,
+    object_prefix: &str,
+    snapshot_prefix: &str,
+) -> RemoteBackups {
+    let mut other_objects = Vec::new();
+    let mut chunks_by_snapshot_number = BTreeMap::<usize, Vec<(usize, Object)>>::new();
+    for object in objects {
+        let chunk = object
+            .key()
+            .and_then(|key| key.strip_prefix(object_prefix))
+            .and_then(|key| key.rsplit_once('/'))
+            .and_then(|(object_name, index)| {
+                if index.is_empty() || !index.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return None;
+                }
+                Some((
+                    parse_backup_object_name(object_name, snapshot_prefix)?,
+                    index.parse::<usize>().ok()?,
+                ))
+            });
+        match chunk {
+            Some((snapshot_number, index)) => chunks_by_snapshot_number
+                .entry(snapshot_number)
+                .or_default()
+                .push((index, object)),
+            None => other_objects.push(object),
+        }
+    }
+    let missing_backups = match chunks_by_snapshot_number.last_key_value() {
+        Some((last_snapshot_number, _)) => (0..*last_snapshot_number)
+            .filter(|snapshot_number| !chunks_by_snapshot_number.contains_key(snapshot_number))
+            .collect(),
+        None => Vec::new(),
+    };
+    let backups = chunks_by_snapshot_number
+        .into_iter()
+        .map(|(snapshot_number, mut chunks)| {
+            chunks.sort_by_key(|(index, _)| *index);
+            RemoteBackup {
+                snapshot_number,
+                object_name: backup_object_name(snapshot_prefix, snapshot_number),
+                chunks: chunks.into_iter().map(|(_, object)| object).collect(),
+            }
+        })
+        .collect();
+    RemoteBackups {
+        backups,
+        missing_backups,
+        other_objects,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(key: &str) -> Object {
+        Object::builder().key(key).build()
+    }
+
+    #[test]
+    fn gap_in_chain_does_not_hide_later_backups() {
+        let RemoteBackups {
+            backups,
+            missing_backups,
+            other_objects,
+        } = remote_backups(
+            vec![
+                object("dataset/backup0/1"),
+                object("dataset/backup0/0"),
+                object("dataset/backup1_backup2/0"),
+                object("dataset/backup2_backup3/0"),
+                object("dataset/backup-offsite0/0"),
+                object("dataset/notes.txt"),
+            ],
+            "dataset/",
+            "backup",
+        );
+        assert_eq!(
+            backups
+                .iter()
+                .map(|backup| (
+                    backup.snapshot_number,
+                    backup.object_name.as_str(),
+                    backup
+                        .chunks
+                        .iter()
+                        .map(|chunk| chunk.key().unwrap_or_default())
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (0, "backup0", vec!["dataset/backup0/0", "dataset/backup0/1"]),
+                (2, "backup1_backup2", vec!["dataset/backup1_backup2/0"]),
+                (3, "backup2_backup3", vec!["dataset/backup2_backup3/0"]),
+            ]
+        );
+        assert_eq!(missing_backups, [1]);
+        assert_eq!(
+            other_objects
+                .iter()
+                .map(|object| object.key().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            ["dataset/backup-offsite0/0", "dataset/notes.txt"]
+        );
+    }
+}
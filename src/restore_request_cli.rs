@@ -0,0 +1,57 @@
+use aws_sdk_s3::types::Tier;
+use clap::Parser;
+use zfs_incremental_backup::{archive_restore::request_restore, run::backup_chain_object_keys};
+
+use crate::{
+    parse_tier::parse_tier,
+    remote_backup_args::RemoteBackupArgs,
+    s3_client::{S3ClientArgs, create_s3_client},
+};
+
+/// Requests S3 to restore the archived (`GLACIER` or `DEEP_ARCHIVE`) objects of a snapshot and all of the snapshots before it, so that they can be downloaded.
+/// Use `restore-wait` to wait until they are restored.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[command(flatten)]
+    remote_backup_args: RemoteBackupArgs,
+    /// The number of the snapshot to restore, for example `3` for `backup3`.
+    /// All of the backups needed to create this snapshot (`backup0`, `backup0_backup1`, ... `backup2_backup3`) are included.
+    #[arg(long)]
+    snapshot: usize,
+    /// How many days the restored copies will be available for downloading
+    #[arg(long, default_value_t = 7)]
+    days: i32,
+    /// `Bulk` is the cheapest and slowest (up to 48 hours for `DEEP_ARCHIVE`).
+    /// `Standard` is faster, and `Expedited` is only available for `GLACIER`.
+    #[arg(long, default_value = "Bulk", value_parser = parse_tier)]
+    tier: Tier,
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+}
+
+pub async fn restore_request_cli(
+    Cli {
+        remote_backup_args:
+            RemoteBackupArgs {
+                bucket,
+                object_prefix,
+                snapshot_prefix,
+            },
+        snapshot,
+        days,
+        tier,
+        s3_client_args,
+    }: Cli,
+) {
+    let client = create_s3_client(s3_client_args).await;
+    let chunks_requested = request_restore(
+        &client,
+        &bucket,
+        &backup_chain_object_keys(&object_prefix, &snapshot_prefix, snapshot),
+        days,
+        tier,
+    )
+    .await
+    .unwrap();
+    println!("Requested restores for {chunks_requested} chunks");
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use clap::Parser;
+use zfs_incremental_backup::{archive_restore::wait_for_restore, run::backup_chain_object_keys};
+
+use crate::{
+    remote_backup_args::RemoteBackupArgs,
+    s3_client::{S3ClientArgs, create_s3_client},
+};
+
+/// Waits until the restores requested with `restore-request` are finished and the objects can be downloaded.
+/// This can be interrupted and ran again, since S3 keeps track of the restores.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[command(flatten)]
+    remote_backup_args: RemoteBackupArgs,
+    /// The number of the snapshot to restore, for example `3` for `backup3`.
+    /// All of the backups needed to create this snapshot (`backup0`, `backup0_backup1`, ... `backup2_backup3`) are included.
+    #[arg(long)]
+    snapshot: usize,
+    /// How many seconds to wait between checking if the objects are restored
+    #[arg(long, default_value_t = 900)]
+    poll_interval: u64,
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+}
+
+pub async fn restore_wait_cli(
+    Cli {
+        remote_backup_args:
+            RemoteBackupArgs {
+                bucket,
+                object_prefix,
+                snapshot_prefix,
+            },
+        snapshot,
+        poll_interval,
+        s3_client_args,
+    }: Cli,
+) {
+    let client = create_s3_client(s3_client_args).await;
+    wait_for_restore(
+        &client,
+        &bucket,
+        &backup_chain_object_keys(&object_prefix, &snapshot_prefix, snapshot),
+        Duration::from_secs(poll_interval),
+        &mut |restored_chunks, total_chunks| {
+            println!("{restored_chunks} / {total_chunks} chunks restored");
+        },
+    )
+    .await
+    .unwrap();
+    println!("All chunks are restored");
+}
@@ -1,17 +1,23 @@
-use std::{borrow::Cow, num::NonZero, path::Path};
+use std::{num::NonZero, path::Path};
 
 use aws_sdk_s3::types::StorageClass;
 use rcs3ud::{AmountLimiter2, OperationScheduler2, S3Dest};
 use serde::{Deserialize, Serialize};
 use zfs_wrapper::{ZfsDataset, ZfsSnapshot};
 
-use crate::backup::{BackupError, BackupSaveData, backup};
+use crate::{
+    backup::{BackupError, BackupSaveData, BackupWarning, backup},
+    ensure_snapshot_with_retry::SnapshotRetryOptions,
+};
 
 /// Actual data
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AutoBackupState {
     pub snapshots_backed_up: usize,
     pub backing_up_progress: Option<BackupSaveData>,
+    /// The estimated size of the `zfs send` output for the snapshot that is being backed up, from `zfs send -nP`
+    #[serde(default)]
+    pub estimated_send_size: Option<u64>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -21,9 +27,67 @@ pub enum AutoBackError<ReserveError, MarkUsedError, SaveError> {
     Save(SaveError),
 }
 
+/// The name of the uploaded "file" which creates snapshot number `snapshot_number`.
+/// This is `backup0` for the first snapshot, and `backup0_backup1`, `backup1_backup2`, etc. for the incremental ones.
+pub fn backup_object_name(snapshot_prefix: &str, snapshot_number: usize) -> String {
+    match snapshot_number.checked_sub(1) {
+        Some(previous_snapshot_number) => format!(
+            "{snapshot_prefix}{previous_snapshot_number}_{snapshot_prefix}{snapshot_number}"
+        ),
+        None => format!("{snapshot_prefix}{snapshot_number}"),
+    }
+}
+
+/// The opposite of [`backup_object_name`]. Returns `None` if the object name isn't the name of a backup with this snapshot prefix.
+pub fn parse_backup_object_name(object_name: &str, snapshot_prefix: &str) -> Option<usize> {
+    let digits_start = object_name
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .len();
+    let snapshot_number = object_name[digits_start..].parse().ok()?;
+    (backup_object_name(snapshot_prefix, snapshot_number) == object_name).then_some(snapshot_number)
+}
+
+/// Gets `n` from a snapshot name that is `{snapshot_prefix}{n}`.
+/// Only digits are allowed after the prefix, so other names that happen to start with the prefix are not matched.
+pub fn parse_snapshot_number(snapshot_name: &str, snapshot_prefix: &str) -> Option<usize> {
+    let snapshot_number = snapshot_name.strip_prefix(snapshot_prefix)?;
+    if snapshot_number.is_empty() || !snapshot_number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    snapshot_number.parse().ok()
+}
+
+/// Checks that a snapshot prefix can't be confused with another prefix.
+/// It can't be empty and can't end with a digit. Otherwise, with the prefixes `backup` and `backup2`, the snapshot `backup23` would look like snapshot 23 of `backup`.
+/// This doesn't stop one prefix from starting with another (`backup` and `backup-offsite`), so targets sharing a bucket should also use different object prefixes.
+pub fn is_valid_snapshot_prefix(snapshot_prefix: &str) -> bool {
+    snapshot_prefix
+        .chars()
+        .last()
+        .is_some_and(|last_char| !last_char.is_ascii_digit())
+}
+
+/// The S3 object keys of every backup needed to create snapshot number `snapshot_number`, in the order they need to be received
+pub fn backup_chain_object_keys(
+    object_prefix: &str,
+    snapshot_prefix: &str,
+    snapshot_number: usize,
+) -> Vec<String> {
+    (0..=snapshot_number)
+        .map(|snapshot_number| {
+            format!(
+                "{object_prefix}{}",
+                backup_object_name(snapshot_prefix, snapshot_number)
+            )
+        })
+        .collect()
+}
+
 /// Takes a snapshot and backs it up, or completes the previous unfinished operation.
 /// The snapshot name is automatic and incremental starting at 0.
 /// Always does an incremental backup from the last backed up snapshot.
+/// Stops before sending if the estimated size of the `zfs send` output is bigger than `max_send_size`.
+/// Returns the size of the uploaded `zfs send` output, in bytes, if it is known.
 pub async fn run<ReserveError, MarkUsedError, SaveError>(
     mut save_data: AutoBackupState,
     dataset: ZfsDataset<'_>,
@@ -33,13 +97,16 @@ pub async fn run<ReserveError, MarkUsedError, SaveError>(
     temp_dir: &Path,
     storage_class: StorageClass,
     chunk_size: NonZero<usize>,
+    snapshot_retry_options: SnapshotRetryOptions,
+    on_warning: &mut impl FnMut(BackupWarning<'_>),
+    max_send_size: Option<u64>,
     client: &aws_sdk_s3::Client,
     amount_limiter: &mut Box<
         dyn AmountLimiter2<ReserveError = ReserveError, MarkUsedError = MarkUsedError> + Send,
     >,
     operation_scheduler: &mut Box<dyn OperationScheduler2 + Send>,
     save: &mut impl AsyncFnMut(&AutoBackupState) -> Result<(), SaveError>,
-) -> Result<(), AutoBackError<ReserveError, MarkUsedError, SaveError>> {
+) -> Result<Option<u64>, AutoBackError<ReserveError, MarkUsedError, SaveError>> {
     if save_data.backing_up_progress.is_none() {
         save_data.backing_up_progress = Some(Default::default());
     }
@@ -49,14 +116,10 @@ pub async fn run<ReserveError, MarkUsedError, SaveError>(
         .checked_sub(1)
         .map(|snapshot_number| format!("{snapshot_prefix}{snapshot_number}"));
     let snapshot_name = format!("{snapshot_prefix}{snapshot_number}");
-    let object_name = if let Some(previous_snapshot_name) = &previous_snapshot_name {
-        Cow::Owned(format!("{previous_snapshot_name}_{snapshot_name}"))
-    } else {
-        Cow::Borrowed(&snapshot_name)
-    };
-    let file_path = temp_dir.join(object_name.to_string());
+    let object_name = backup_object_name(snapshot_prefix, snapshot_number);
+    let file_path = temp_dir.join(&object_name);
     let object_key = format!("{object_prefix}{object_name}");
-    backup(
+    let size = backup(
         save_data.backing_up_progress.clone().unwrap_or_default(),
         ZfsSnapshot {
             dataset: dataset.clone(),
@@ -73,8 +136,13 @@ pub async fn run<ReserveError, MarkUsedError, SaveError>(
         amount_limiter,
         operation_scheduler,
         chunk_size,
-        &mut async |backup_save_data| {
+        snapshot_retry_options,
+        on_warning,
+        save_data.estimated_send_size,
+        max_send_size,
+        &mut async |backup_save_data, estimated_send_size| {
             save_data.backing_up_progress = Some(backup_save_data.clone());
+            save_data.estimated_send_size = estimated_send_size;
             save(&save_data).await
         },
     )
@@ -82,6 +150,58 @@ pub async fn run<ReserveError, MarkUsedError, SaveError>(
     .map_err(AutoBackError::Backup)?;
     save_data.snapshots_backed_up += 1;
     save_data.backing_up_progress = None;
+    save_data.estimated_send_size = None;
     save(&save_data).await.map_err(AutoBackError::Save)?;
-    Ok(())
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_snapshot_number_only_matches_digits() {
+        assert_eq!(
+            parse_snapshot_number("pool/ds@backup12", "pool/ds@backup"),
+            Some(12)
+        );
+        assert_eq!(
+            parse_snapshot_number("pool/ds@backup", "pool/ds@backup"),
+            None
+        );
+        assert_eq!(
+            parse_snapshot_number("pool/ds@backup+1", "pool/ds@backup"),
+            None
+        );
+        assert_eq!(
+            parse_snapshot_number("pool/ds@backup-b3", "pool/ds@backup"),
+            None
+        );
+        assert_eq!(
+            parse_snapshot_number("pool/ds@other1", "pool/ds@backup"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_backup_object_name_is_the_opposite_of_backup_object_name() {
+        for snapshot_number in [0, 1, 2, 10, 123] {
+            assert_eq!(
+                parse_backup_object_name(&backup_object_name("backup", snapshot_number), "backup"),
+                Some(snapshot_number)
+            );
+        }
+        assert_eq!(parse_backup_object_name("backup1", "backup"), None);
+        assert_eq!(parse_backup_object_name("backup0_backup2", "backup"), None);
+        assert_eq!(parse_backup_object_name("backup00", "backup"), None);
+        assert_eq!(parse_backup_object_name("other0", "backup"), None);
+    }
+
+    #[test]
+    fn snapshot_prefixes_ending_with_digits_are_invalid() {
+        assert!(is_valid_snapshot_prefix("backup"));
+        assert!(is_valid_snapshot_prefix("aws-backup"));
+        assert!(!is_valid_snapshot_prefix("backup2"));
+        assert!(!is_valid_snapshot_prefix(""));
+    }
 }
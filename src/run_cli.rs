@@ -1,14 +1,33 @@
 use crate::{
-    init_cli::{AutoBackupConfig, AutoBackupFileData},
+    parse_metrics_textfile::parse_metrics_textfile,
     parse_storage_class::parse_storage_class,
-    run::run,
+    s3_client::{S3ClientArgs, create_s3_client},
 };
-use aws_config::{BehaviorVersion, Region};
-use aws_sdk_s3::{config::Credentials, types::StorageClass};
+use aws_sdk_s3::types::StorageClass;
 use clap::Parser;
 use rcs3ud::{AmountLimiter2, NoOpAmountLimiter2, NoOpOperationScheduler2, OperationScheduler2};
-use std::{num::NonZero, path::PathBuf};
-use tokio::fs::{read_to_string, write};
+use std::{
+    io,
+    num::NonZero,
+    path::PathBuf,
+    process::exit,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::fs::read_to_string;
+use zfs_incremental_backup::{
+    backup::{BackupError, BackupSaveData, BackupWarning},
+    ensure_snapshot_with_retry::SnapshotRetryOptions,
+    lock_save_data::{LockSaveDataError, lock_save_data},
+    metrics_textfile::{RunMetrics, write_metrics_textfile},
+    ping_healthcheck::{HealthcheckPing, ping_healthcheck},
+    prune_snapshots::prune_snapshots,
+    run::{AutoBackError, AutoBackupState, run},
+    run_hook::{RunHookError, run_hook},
+    save_data::{AutoBackupConfig, ParseSaveDataError, parse_save_data},
+    sd_notify::sd_notify,
+    write_save_data::write_save_data,
+    zfs_commands::{ZfsCommandError, zfs_dataset_name},
+};
 
 /// Example (what I do):
 /// zpool: "para-z"
@@ -37,11 +56,133 @@ pub struct Cli {
     /// The maximum object size, in bytes. If the file is bigger than the max object size, then a file will be split up into multiple S3 objects labeled `0`, `1`, `2`, ...
     #[arg(long)]
     chunk_size: NonZero<usize>,
-    /// Use development S3 server (minio)
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+    /// How many times to retry taking the snapshot if it fails for a reason that could be temporary, such as the pool being busy.
+    /// Errors such as permission denied or the dataset not existing are not retried.
+    #[arg(long, default_value_t = 5)]
+    snapshot_retries: u32,
+    /// How many seconds to wait before the first snapshot retry. The delay doubles after each retry.
+    #[arg(long, default_value_t = 10)]
+    snapshot_retry_delay: u64,
+    /// Don't back up a snapshot if `zfs send` is estimated to output more than this many bytes, such as if a big file was accidentally added to the dataset.
+    /// The snapshot is kept, so the backup can be continued by running again with a bigger limit (or without a limit).
+    #[arg(long)]
+    max_send_size: Option<u64>,
+    /// After a successful backup, destroy local backup snapshots except for the last this many.
+    /// If not set, snapshots are never destroyed.
+    #[arg(long)]
+    keep_snapshots: Option<NonZero<usize>>,
+    /// A shell command to run before taking a new snapshot, such as a command which flushes a database to disk.
+    /// It is not run when continuing a backup of a snapshot that was already taken.
+    #[arg(long)]
+    pre_backup: Option<String>,
+    /// A shell command to run after a snapshot is backed up.
+    #[arg(long)]
+    post_backup: Option<String>,
+    /// How many seconds to wait for `--pre-backup` and `--post-backup` to finish before killing them
+    #[arg(long, default_value_t = 300)]
+    hook_timeout: u64,
+    /// If a hook fails, print a warning and keep going instead of stopping the backup
+    #[arg(long)]
+    warn_on_hook_failure: bool,
+    /// After running, write Prometheus metrics to this file, for node_exporter's textfile collector.
+    /// The file name must end with `.prom`.
+    #[arg(long, value_parser = parse_metrics_textfile)]
+    metrics_textfile: Option<PathBuf>,
+    /// A healthchecks.io style ping URL, such as `https://hc-ping.com/<uuid>`.
+    /// `/start` is pinged when starting, the URL itself is pinged after a successful backup, and `/fail` is pinged (with the error) if the backup fails.
     #[arg(long)]
-    dev: bool,
-    #[arg(long, default_value = "http://localhost:9000")]
-    dev_endpoint: String,
+    ping_url: Option<String>,
+    /// Only print errors and warnings
+    #[arg(long)]
+    quiet: bool,
+}
+
+/// Any error that doesn't have a more specific exit code, such as not having enough space for the `zfs send` file
+const EXIT_OTHER_ERROR: i32 = 1;
+/// The save data file couldn't be read or is invalid
+const EXIT_CONFIG_ERROR: i32 = 3;
+/// Uploading to S3 failed
+const EXIT_REMOTE_ERROR: i32 = 4;
+/// A `zfs` command failed
+const EXIT_ZFS_ERROR: i32 = 5;
+/// Another instance is already using the save data file, such as when a backup takes longer than the time between runs
+const EXIT_LOCKED: i32 = 6;
+
+/// Everything that can make `run` fail
+#[derive(Debug)]
+enum RunCliError {
+    Lock(LockSaveDataError),
+    ReadSaveData(io::Error),
+    ParseSaveData(ParseSaveDataError),
+    PreBackupHook(RunHookError),
+    Backup(AutoBackError<(), (), io::Error>),
+    PostBackupHook(RunHookError),
+    Prune(ZfsCommandError),
+}
+
+impl RunCliError {
+    /// The exit code, so that scripts can tell what kind of error happened
+    fn exit_code(&self) -> i32 {
+        match self {
+            RunCliError::Backup(AutoBackError::Backup(
+                BackupError::Snapshot(_)
+                | BackupError::Hold(_)
+                | BackupError::SizeEstimate(_)
+                | BackupError::Send(_)
+                | BackupError::Release(_),
+            ))
+            | RunCliError::Prune(_) => EXIT_ZFS_ERROR,
+            RunCliError::ReadSaveData(_) | RunCliError::ParseSaveData(_) => EXIT_CONFIG_ERROR,
+            RunCliError::Backup(AutoBackError::Backup(BackupError::Upload(_))) => EXIT_REMOTE_ERROR,
+            RunCliError::Backup(AutoBackError::Backup(
+                BackupError::Save(_)
+                | BackupError::Statvfs(_)
+                | BackupError::NotEnoughSpace { .. }
+                | BackupError::TooBig { .. }
+                | BackupError::Open(_)
+                | BackupError::RemoveFile(_),
+            ))
+            | RunCliError::Backup(AutoBackError::Save(_))
+            | RunCliError::Lock(_)
+            | RunCliError::PreBackupHook(_)
+            | RunCliError::PostBackupHook(_) => EXIT_OTHER_ERROR,
+        }
+    }
+}
+
+/// A short description of what is happening, shown in `systemctl status`
+fn status_message(
+    AutoBackupState {
+        snapshots_backed_up,
+        backing_up_progress,
+        estimated_send_size,
+    }: &AutoBackupState,
+    chunk_size: NonZero<usize>,
+) -> String {
+    let estimate = match estimated_send_size {
+        Some(size) => format!(
+            " (about {size} bytes, {} chunks)",
+            size.div_ceil(chunk_size.get() as u64).max(1)
+        ),
+        None => String::new(),
+    };
+    match backing_up_progress {
+        None => format!("Backed up {snapshots_backed_up} snapshots"),
+        Some(BackupSaveData::CreatingSnapshot) => {
+            format!("Creating snapshot {snapshots_backed_up}")
+        }
+        Some(BackupSaveData::SendingToFile) => {
+            format!("Sending snapshot {snapshots_backed_up} to a file{estimate}")
+        }
+        Some(BackupSaveData::Uploading(_)) => {
+            format!("Uploading snapshot {snapshots_backed_up}{estimate}")
+        }
+        Some(BackupSaveData::RemovingFile) => {
+            format!("Removing the file for snapshot {snapshots_backed_up}")
+        }
+    }
 }
 
 pub async fn run_cli(
@@ -50,61 +191,201 @@ pub async fn run_cli(
         temp_dir,
         storage_class,
         chunk_size,
-        dev,
-        dev_endpoint,
+        s3_client_args,
+        snapshot_retries,
+        snapshot_retry_delay,
+        max_send_size,
+        keep_snapshots,
+        pre_backup,
+        post_backup,
+        hook_timeout,
+        warn_on_hook_failure,
+        metrics_textfile,
+        ping_url,
+        quiet,
     }: Cli,
 ) {
-    let client = if dev {
-        aws_sdk_s3::Client::from_conf(
-            aws_sdk_s3::config::Builder::default()
-                .behavior_version_latest()
-                .endpoint_url(dev_endpoint)
-                .credentials_provider(Credentials::new(
-                    "minioadmin",
-                    "minioadmin",
-                    None,
-                    None,
-                    "minio",
-                ))
-                .region(Region::from_static("us-east-1"))
-                .force_path_style(true)
-                .build(),
-        )
-    } else {
-        aws_sdk_s3::Client::new(&aws_config::load_defaults(BehaviorVersion::latest()).await)
+    let hook_timeout = Duration::from_secs(hook_timeout);
+    // If another instance is still backing up, this run didn't fail, so monitoring isn't told about it.
+    // The lock is held until the metrics are written, so that the instances don't write the metrics at the same time.
+    let mut lock_error = None;
+    let _lock = match lock_save_data(&save_data_path) {
+        Ok(lock) => Some(lock),
+        Err(LockSaveDataError::Locked) => {
+            if !quiet {
+                println!("Another instance is already using {save_data_path:?}");
+            }
+            exit(EXIT_LOCKED);
+        }
+        Err(e) => {
+            lock_error = Some(e);
+            None
+        }
     };
-
-    let mut file_data =
-        ron::from_str::<AutoBackupFileData>(&read_to_string(&save_data_path).await.unwrap())
-            .unwrap();
-    let AutoBackupConfig {
-        dataset,
-        bucket,
-        snapshot_prefix,
-        object_prefix,
-    } = file_data.config.clone();
-    run(
-        file_data.state.clone(),
-        dataset,
-        &bucket,
-        &snapshot_prefix,
-        &object_prefix,
-        &PathBuf::from(temp_dir),
-        storage_class,
-        chunk_size,
-        &client,
-        &mut (Box::new(NoOpAmountLimiter2)
-            as Box<dyn AmountLimiter2<ReserveError = (), MarkUsedError = ()> + Send>),
-        &mut (Box::new(NoOpOperationScheduler2) as Box<dyn OperationScheduler2 + Send>),
-        &mut async |state| {
-            file_data.state = state.clone();
-            write(
-                &save_data_path,
-                ron::ser::to_string_pretty(&file_data, Default::default()).unwrap(),
+    // A failed ping doesn't stop the backup, since the monitoring service will notice the missing ping anyways
+    if let Some(ping_url) = &ping_url
+        && let Err(e) = ping_healthcheck(ping_url, HealthcheckPing::Start).await
+    {
+        eprintln!("Failed to ping {ping_url}: {e:?}");
+    }
+    let start_time = SystemTime::now();
+    let start_instant = Instant::now();
+    // Known after the save data is read, for the metrics
+    let mut dataset_name = None;
+    let mut snapshots_backed_up = None;
+    // Known after a backup is finished
+    let mut backup_bytes = None;
+    let result = async {
+        if let Some(e) = lock_error {
+            return Err(RunCliError::Lock(e));
+        }
+        let mut file_data = parse_save_data(
+            &read_to_string(&save_data_path)
+                .await
+                .map_err(RunCliError::ReadSaveData)?,
+        )
+        .map_err(RunCliError::ParseSaveData)?;
+        let AutoBackupConfig {
+            dataset,
+            bucket,
+            snapshot_prefix,
+            object_prefix,
+        } = file_data.config.clone();
+        let snapshot_name = format!(
+            "{}@{snapshot_prefix}{}",
+            zfs_dataset_name(&dataset),
+            file_data.state.snapshots_backed_up
+        );
+        dataset_name = Some(zfs_dataset_name(&dataset));
+        snapshots_backed_up = Some(file_data.state.snapshots_backed_up);
+        let client = create_s3_client(s3_client_args).await;
+        let is_new_snapshot = matches!(
+            file_data.state.backing_up_progress,
+            None | Some(BackupSaveData::CreatingSnapshot)
+        );
+        if is_new_snapshot
+            && let Some(pre_backup) = &pre_backup
+            && let Err(e) = run_hook(pre_backup, &snapshot_name, hook_timeout).await
+        {
+            if !warn_on_hook_failure {
+                return Err(RunCliError::PreBackupHook(e));
+            }
+            eprintln!("Pre backup hook failed: {e:?}");
+        }
+        backup_bytes = run(
+            file_data.state.clone(),
+            dataset.clone(),
+            &bucket,
+            &snapshot_prefix,
+            &object_prefix,
+            &PathBuf::from(temp_dir),
+            storage_class,
+            chunk_size,
+            SnapshotRetryOptions {
+                max_retries: snapshot_retries,
+                initial_delay: Duration::from_secs(snapshot_retry_delay),
+            },
+            &mut |warning| match warning {
+                BackupWarning::SnapshotRetry {
+                    error,
+                    delay,
+                    retry,
+                } => eprintln!(
+                    "Failed to take snapshot: {error:?}. Retrying in {delay:?} (retry {retry} of {snapshot_retries})"
+                ),
+                BackupWarning::HoldDenied(e) => eprintln!(
+                    "Not allowed to hold {snapshot_name}, so it is backed up without a hold: {e:?}"
+                ),
+                BackupWarning::ReleaseDenied(e) => {
+                    eprintln!("Not allowed to release the hold on {snapshot_name}: {e:?}")
+                }
+            },
+            max_send_size,
+            &client,
+            &mut (Box::new(NoOpAmountLimiter2)
+                as Box<dyn AmountLimiter2<ReserveError = (), MarkUsedError = ()> + Send>),
+            &mut (Box::new(NoOpOperationScheduler2) as Box<dyn OperationScheduler2 + Send>),
+            &mut async |state| {
+                if let Err(e) = sd_notify(&format!("STATUS={}", status_message(state, chunk_size)))
+                {
+                    eprintln!("Failed to send status to systemd: {e:?}");
+                }
+                file_data.state = state.clone();
+                snapshots_backed_up = Some(state.snapshots_backed_up);
+                write_save_data(
+                    &save_data_path,
+                    &ron::ser::to_string_pretty(&file_data, Default::default()).unwrap(),
+                )
+                .await
+            },
+        )
+        .await
+        .map_err(RunCliError::Backup)?;
+        if let Some(post_backup) = &post_backup
+            && let Err(e) = run_hook(post_backup, &snapshot_name, hook_timeout).await
+        {
+            if !warn_on_hook_failure {
+                return Err(RunCliError::PostBackupHook(e));
+            }
+            eprintln!("Post backup hook failed: {e:?}");
+        }
+        if let Some(keep_snapshots) = keep_snapshots {
+            let destroyed = prune_snapshots(
+                &dataset,
+                &snapshot_prefix,
+                file_data.state.snapshots_backed_up,
+                keep_snapshots,
             )
             .await
-        },
-    )
-    .await
-    .unwrap();
+            .map_err(RunCliError::Prune)?;
+            if !quiet {
+                for snapshot in destroyed {
+                    println!("Destroyed {snapshot}");
+                }
+            }
+        }
+        if !quiet {
+            println!(
+                "Backed up {snapshot_name} ({} snapshots backed up)",
+                file_data.state.snapshots_backed_up
+            );
+        }
+        Ok::<(), RunCliError>(())
+    }
+    .await;
+    // Monitoring is only told about the result after everything, including the hooks, is done
+    if let Some(metrics_textfile) = metrics_textfile
+        && let Err(e) = write_metrics_textfile(
+            &metrics_textfile,
+            RunMetrics {
+                dataset: dataset_name.as_deref(),
+                success: result.is_ok(),
+                start_time,
+                duration: start_instant.elapsed(),
+                snapshots_backed_up,
+                backup_bytes,
+                backup_objects: backup_bytes
+                    .map(|bytes| bytes.div_ceil(chunk_size.get() as u64).max(1)),
+            },
+        )
+        .await
+    {
+        eprintln!("Failed to write metrics: {e:?}");
+    }
+    if let Some(ping_url) = &ping_url
+        && let Err(e) = ping_healthcheck(
+            ping_url,
+            match &result {
+                Ok(()) => HealthcheckPing::Success,
+                Err(e) => HealthcheckPing::Fail(format!("{e:#?}")),
+            },
+        )
+        .await
+    {
+        eprintln!("Failed to ping {ping_url}: {e:?}");
+    }
+    if let Err(e) = result {
+        eprintln!("Backup failed: {e:?}");
+        exit(e.exit_code());
+    }
 }
@@ -0,0 +1,55 @@
+use std::{
+    io,
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+
+use tokio::{process::Command, time::timeout};
+
+#[derive(Debug)]
+pub enum RunHookError {
+    Spawn(io::Error),
+    Wait(io::Error),
+    /// The command didn't finish in time, and was killed
+    Timeout,
+    Failed(ExitStatus),
+}
+
+/// Runs a user provided shell command with `sh -c`, such as a command that flushes a database before a snapshot is taken.
+/// The command gets the full name of the snapshot in the `ZFS_INCREMENTAL_BACKUP_SNAPSHOT` environment variable.
+/// The command runs in its own process group, so that if it times out, any processes that it started are killed too.
+pub async fn run_hook(
+    command: &str,
+    snapshot: &str,
+    max_duration: Duration,
+) -> Result<(), RunHookError> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("ZFS_INCREMENTAL_BACKUP_SNAPSHOT", snapshot)
+        .stdin(Stdio::null())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(RunHookError::Spawn)?;
+    let status = match timeout(max_duration, child.wait()).await {
+        Ok(status) => status.map_err(RunHookError::Wait)?,
+        Err(_) => {
+            if let Some(pid) = child.id() {
+                // The process group id is the pid of `sh`, and a negative pid kills the whole group.
+                // If this fails, `sh` itself is still killed below.
+                let _ = Command::new("kill")
+                    .args(["-KILL", "--", &format!("-{pid}")])
+                    .stdin(Stdio::null())
+                    .status()
+                    .await;
+            }
+            child.kill().await.map_err(RunHookError::Wait)?;
+            return Err(RunHookError::Timeout);
+        }
+    };
+    if !status.success() {
+        return Err(RunHookError::Failed(status));
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use aws_config::{BehaviorVersion, Region, sts::AssumeRoleProvider};
+use aws_sdk_s3::config::{Credentials, retry::RetryConfig};
+use clap::Args;
+use std::{num::NonZero, time::Duration};
+
+#[derive(Debug, Args)]
+pub struct S3ClientArgs {
+    /// Use development S3 server (minio)
+    #[arg(long)]
+    dev: bool,
+    #[arg(long, default_value = "http://localhost:9000")]
+    dev_endpoint: String,
+    /// Use an S3 compatible service instead of AWS, such as MinIO, Backblaze B2, Wasabi, or Ceph RGW.
+    /// Credentials are read the same way as for AWS, for example from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+    #[arg(long, conflicts_with = "dev")]
+    endpoint_url: Option<String>,
+    /// Overrides the region from the AWS config. Some S3 compatible services need a specific region.
+    #[arg(long, conflicts_with = "dev")]
+    region: Option<String>,
+    /// Use `{endpoint}/{bucket}` URLs instead of `{bucket}.{endpoint}`. Needed by many S3 compatible services.
+    #[arg(long, conflicts_with = "dev")]
+    force_path_style: bool,
+    /// Use credentials and settings from this profile in `~/.aws/config` and `~/.aws/credentials`, instead of the default profile
+    #[arg(long, conflicts_with = "dev")]
+    profile: Option<String>,
+    /// Assume this IAM role (with STS) and use its credentials, such as a role in a separate AWS account which owns the bucket.
+    /// The role is assumed using the credentials that would be used otherwise.
+    #[arg(long, conflicts_with = "dev")]
+    role_arn: Option<String>,
+    /// The external ID to give when assuming `--role-arn`, if the role's trust policy requires one
+    #[arg(long, requires = "role_arn")]
+    external_id: Option<String>,
+    /// The maximum number of times to try each S3 request.
+    /// Requests that fail for a reason that could be temporary, such as throttling, server errors, or the connection being reset, are retried with exponential backoff.
+    /// Errors such as access denied are not retried.
+    #[arg(long, default_value = "10")]
+    max_attempts: NonZero<u32>,
+    /// The maximum number of seconds to wait between retries of an S3 request
+    #[arg(long, default_value_t = 60)]
+    max_backoff: u64,
+}
+
+pub async fn create_s3_client(
+    S3ClientArgs {
+        dev,
+        dev_endpoint,
+        endpoint_url,
+        region,
+        force_path_style,
+        profile,
+        role_arn,
+        external_id,
+        max_attempts,
+        max_backoff,
+    }: S3ClientArgs,
+) -> aws_sdk_s3::Client {
+    let retry_config = RetryConfig::standard()
+        .with_max_attempts(max_attempts.get())
+        .with_max_backoff(Duration::from_secs(max_backoff));
+    if dev {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::config::Builder::default()
+                .behavior_version_latest()
+                .endpoint_url(dev_endpoint)
+                .credentials_provider(Credentials::new(
+                    "minioadmin",
+                    "minioadmin",
+                    None,
+                    None,
+                    "minio",
+                ))
+                .region(Region::from_static("us-east-1"))
+                .force_path_style(true)
+                .retry_config(retry_config)
+                .build(),
+        )
+    } else {
+        let mut config_loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(endpoint_url) = endpoint_url {
+            config_loader = config_loader.endpoint_url(endpoint_url);
+        }
+        if let Some(region) = region {
+            config_loader = config_loader.region(Region::new(region));
+        }
+        if let Some(profile) = profile {
+            config_loader = config_loader.profile_name(profile);
+        }
+        let sdk_config = config_loader.load().await;
+        let mut config_builder = aws_sdk_s3::config::Builder::from(&sdk_config)
+            .force_path_style(force_path_style)
+            .retry_config(retry_config);
+        if let Some(role_arn) = role_arn {
+            let mut assume_role = AssumeRoleProvider::builder(role_arn)
+                .session_name("zfs-incremental-backup")
+                .configure(&sdk_config);
+            if let Some(external_id) = external_id {
+                assume_role = assume_role.external_id(external_id);
+            }
+            config_builder = config_builder.credentials_provider(assume_role.build().await);
+        }
+        aws_sdk_s3::Client::from_conf(config_builder.build())
+    }
+}
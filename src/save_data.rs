@@ -0,0 +1,98 @@
+use ron::error::SpannedError;
+use serde::{Deserialize, Serialize};
+use zfs_wrapper::ZfsDataset;
+
+use crate::run::AutoBackupState;
+
+/// The version of the save data file format.
+/// Increase this when changing the format in a way that older versions of this program can't read, and add a migration to [`parse_save_data`].
+pub const SAVE_DATA_VERSION: u32 = 1;
+
+/// Configuration that should not change for the lifetime of this file, unless you change the zpool / dataset name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoBackupConfig<'a> {
+    pub dataset: ZfsDataset<'a>,
+    pub bucket: String,
+    pub snapshot_prefix: String,
+    pub object_prefix: String,
+}
+
+/// The config and state are in the same file so that the user doesn't accidentally specify the wrong config and state
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AutoBackupFileData<'a> {
+    /// Files created before the format was versioned don't have this field, and are version 0
+    #[serde(default)]
+    pub version: u32,
+    pub config: AutoBackupConfig<'a>,
+    pub state: AutoBackupState,
+}
+
+#[derive(Debug)]
+pub enum ParseSaveDataError {
+    Ron(SpannedError),
+    /// The file was made by a newer version of this program
+    UnsupportedVersion(u32),
+}
+
+/// Parses a save data file, migrating it to [`SAVE_DATA_VERSION`] if it was made by an older version of this program
+pub fn parse_save_data(save_data: &str) -> Result<AutoBackupFileData<'static>, ParseSaveDataError> {
+    let mut file_data =
+        ron::from_str::<AutoBackupFileData>(save_data).map_err(ParseSaveDataError::Ron)?;
+    if file_data.version > SAVE_DATA_VERSION {
+        return Err(ParseSaveDataError::UnsupportedVersion(file_data.version));
+    }
+    // Version 0 only differs by not having fields which have defaults
+    file_data.version = SAVE_DATA_VERSION;
+    Ok(file_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::BackupSaveData;
+
+    use super::*;
+
+    /// A save data file made before the version and estimated send size fields were added
+    const BASELINE_SAVE_DATA: &str = r#"(
+    config: (
+        dataset: (
+            zpool: "pool",
+            dataset: "dataset",
+        ),
+        bucket: "bucket",
+        snapshot_prefix: "backup",
+        object_prefix: "dataset/",
+    ),
+    state: (
+        snapshots_backed_up: 3,
+        backing_up_progress: Some(SendingToFile),
+    ),
+)"#;
+
+    #[test]
+    fn file_without_version_is_upgraded() {
+        let file_data = parse_save_data(BASELINE_SAVE_DATA).unwrap();
+        assert_eq!(file_data.version, SAVE_DATA_VERSION);
+    }
+
+    #[test]
+    fn file_without_estimated_send_size_parses() {
+        let file_data = parse_save_data(BASELINE_SAVE_DATA).unwrap();
+        assert_eq!(file_data.config.snapshot_prefix, "backup");
+        assert_eq!(file_data.state.snapshots_backed_up, 3);
+        assert!(matches!(
+            file_data.state.backing_up_progress,
+            Some(BackupSaveData::SendingToFile)
+        ));
+        assert_eq!(file_data.state.estimated_send_size, None);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let save_data = BASELINE_SAVE_DATA.replacen("(\n", "(\n    version: 2,\n", 1);
+        assert!(matches!(
+            parse_save_data(&save_data),
+            Err(ParseSaveDataError::UnsupportedVersion(2))
+        ));
+    }
+}
@@ -0,0 +1,35 @@
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::{
+    env, io,
+    os::unix::{
+        ffi::OsStrExt,
+        net::{SocketAddr, UnixDatagram},
+    },
+};
+
+/// Sends a message such as `STATUS=Uploading` to systemd.
+/// Does nothing if this program was not started by systemd with `NotifyAccess` enabled (`NOTIFY_SOCKET` is not set).
+pub fn sd_notify(message: &str) -> io::Result<()> {
+    let Some(notify_socket) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound()?;
+    let address = notify_socket_address(notify_socket.as_bytes())?;
+    socket.send_to_addr(message.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// Paths starting with `@` are abstract sockets, which only exist on Linux
+#[cfg(target_os = "linux")]
+fn notify_socket_address(notify_socket: &[u8]) -> io::Result<SocketAddr> {
+    match notify_socket.strip_prefix(b"@") {
+        Some(abstract_name) => SocketAddr::from_abstract_name(abstract_name),
+        None => SocketAddr::from_pathname(std::ffi::OsStr::from_bytes(notify_socket)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn notify_socket_address(notify_socket: &[u8]) -> io::Result<SocketAddr> {
+    SocketAddr::from_pathname(std::ffi::OsStr::from_bytes(notify_socket))
+}
@@ -0,0 +1,77 @@
+use crate::{
+    backup::BackupSaveData,
+    run::{AutoBackupState, is_valid_snapshot_prefix},
+    save_data::{AutoBackupConfig, AutoBackupFileData},
+    zfs_commands::{ZfsCommandError, zfs_dataset_name, zfs_exists},
+};
+
+/// Checks the save data for problems that would make `run` fail, without changing anything.
+/// Returns a description of each problem that was found.
+pub async fn validate(
+    AutoBackupFileData {
+        config:
+            AutoBackupConfig {
+                dataset,
+                bucket,
+                snapshot_prefix,
+                object_prefix,
+            },
+        state:
+            AutoBackupState {
+                snapshots_backed_up,
+                backing_up_progress,
+                estimated_send_size: _,
+            },
+        version: _,
+    }: &AutoBackupFileData<'_>,
+    client: &aws_sdk_s3::Client,
+) -> Result<Vec<String>, ZfsCommandError> {
+    let mut problems = Vec::new();
+    if !is_valid_snapshot_prefix(snapshot_prefix) {
+        problems.push(
+            "The snapshot prefix is empty or ends with a digit, so its snapshots could be confused with snapshots with a longer prefix".to_owned(),
+        );
+    }
+    let dataset_name = zfs_dataset_name(dataset);
+    if zfs_exists(&dataset_name).await? {
+        // The last backed up snapshot is needed to do the next incremental `zfs send`
+        if let Some(last_snapshot_number) = snapshots_backed_up.checked_sub(1) {
+            let snapshot = format!("{dataset_name}@{snapshot_prefix}{last_snapshot_number}");
+            if !zfs_exists(&snapshot).await? {
+                problems.push(format!(
+                    "The last backed up snapshot, {snapshot}, does not exist, so the next incremental backup can't be made"
+                ));
+            }
+        }
+        // After the snapshot was created, it's needed to continue the backup
+        if matches!(
+            backing_up_progress,
+            Some(
+                BackupSaveData::SendingToFile
+                    | BackupSaveData::Uploading(_)
+                    | BackupSaveData::RemovingFile
+            )
+        ) {
+            let snapshot = format!("{dataset_name}@{snapshot_prefix}{snapshots_backed_up}");
+            if !zfs_exists(&snapshot).await? {
+                problems.push(format!(
+                    "The snapshot being backed up, {snapshot}, does not exist, so the backup can't be continued"
+                ));
+            }
+        }
+    } else {
+        problems.push(format!("The dataset {dataset_name} does not exist"));
+    }
+    // Listing is used instead of `HeadBucket`, which the policy from `print-iam-policy` doesn't allow, since it only allows listing the object prefix
+    if let Err(e) = client
+        .list_objects_v2()
+        .bucket(bucket)
+        .prefix(object_prefix)
+        .max_keys(1)
+        .send()
+        .await
+    {
+        problems.push(format!("Can't access the bucket {bucket}: {e:?}"));
+    }
+    Ok(problems)
+}
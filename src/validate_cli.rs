@@ -0,0 +1,42 @@
+use std::process::exit;
+
+use clap::Parser;
+use tokio::fs::read_to_string;
+use zfs_incremental_backup::{save_data::parse_save_data, validate::validate};
+
+use crate::s3_client::{S3ClientArgs, create_s3_client};
+
+/// Checks the save data file for problems, such as the dataset, the last backed up snapshot, or the bucket not existing.
+/// Exits with an error if there are problems.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    #[arg(long)]
+    save_data_path: String,
+    #[command(flatten)]
+    s3_client_args: S3ClientArgs,
+}
+
+pub async fn validate_cli(
+    Cli {
+        save_data_path,
+        s3_client_args,
+    }: Cli,
+) {
+    let file_data = match parse_save_data(&read_to_string(&save_data_path).await.unwrap()) {
+        Ok(file_data) => file_data,
+        Err(e) => {
+            println!("The save data file is invalid: {e:?}");
+            exit(1);
+        }
+    };
+    let client = create_s3_client(s3_client_args).await;
+    let problems = validate(&file_data, &client).await.unwrap();
+    if problems.is_empty() {
+        println!("No problems found");
+    } else {
+        for problem in &problems {
+            println!("{problem}");
+        }
+        exit(1);
+    }
+}
@@ -0,0 +1,53 @@
+use std::{io, path::Path};
+
+use tokio::{
+    fs::{File, hard_link, remove_file, rename},
+    io::AsyncWriteExt,
+};
+
+/// Writes `contents` to `{path}.tmp` and makes sure that it's saved to the disk
+async fn write_temp_file(path: &str, contents: &str) -> io::Result<String> {
+    let temp_path = format!("{path}.tmp");
+    let mut file = File::create(&temp_path).await?;
+    file.write_all(contents.as_bytes()).await?;
+    file.sync_all().await?;
+    Ok(temp_path)
+}
+
+/// Makes sure that renames and links in the directory containing `path` are saved to the disk
+async fn sync_parent_dir(path: &str) -> io::Result<()> {
+    let parent = match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent).await?.sync_all().await
+}
+
+/// Replaces the save data file without the risk of losing it if the program crashes or the power goes out while writing.
+/// The new contents are written to a temporary file which is then renamed over the save data file.
+/// The previous version of the save data file is kept as `{path}.bak`.
+pub async fn write_save_data(path: &str, contents: &str) -> io::Result<()> {
+    let temp_path = write_temp_file(path, contents).await?;
+    let backup_path = format!("{path}.bak");
+    match remove_file(&backup_path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    match hard_link(path, &backup_path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    rename(&temp_path, path).await?;
+    sync_parent_dir(path).await
+}
+
+/// Creates a new save data file, failing with [`io::ErrorKind::AlreadyExists`] if there already is one.
+/// Like [`write_save_data`], a crash can't leave a partially written file.
+pub async fn create_save_data(path: &str, contents: &str) -> io::Result<()> {
+    let temp_path = write_temp_file(path, contents).await?;
+    // Unlike renaming, linking fails if the save data file already exists
+    let result = hard_link(&temp_path, path).await;
+    remove_file(&temp_path).await?;
+    result?;
+    sync_parent_dir(path).await
+}
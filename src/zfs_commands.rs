@@ -0,0 +1,125 @@
+use std::{io, process::Stdio};
+
+use tokio::process::Command;
+use zfs_wrapper::{ZfsDataset, ZfsSnapshot};
+
+/// The tag of the holds placed on snapshots that are being backed up
+pub const HOLD_TAG: &str = "zfs-incremental-backup";
+
+#[derive(Debug)]
+pub enum ZfsCommandError {
+    Spawn(io::Error),
+    /// The `zfs` command exited unsuccessfully. Contains its stderr.
+    Failed(String),
+}
+
+impl ZfsCommandError {
+    /// If the command failed because the user doesn't have the `zfs allow` permission for it
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(self, ZfsCommandError::Failed(stderr) if stderr.contains("permission denied"))
+    }
+}
+
+/// Runs a `zfs` command and returns its stdout.
+/// If the command fails with an error containing `ignored_error`, it is treated as a success.
+async fn zfs(args: &[&str], ignored_error: Option<&str>) -> Result<String, ZfsCommandError> {
+    let output = Command::new("zfs")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(ZfsCommandError::Spawn)?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success()
+        && !ignored_error.is_some_and(|ignored_error| stderr.contains(ignored_error))
+    {
+        return Err(ZfsCommandError::Failed(stderr.into_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The full name of a dataset, such as `zpool/dataset`
+pub fn zfs_dataset_name(dataset: &ZfsDataset<'_>) -> String {
+    format!("{}/{}", dataset.zpool, dataset.dataset)
+}
+
+/// The full name of a snapshot, such as `zpool/dataset@snapshot`
+pub fn zfs_snapshot_name(snapshot: &ZfsSnapshot<'_>) -> String {
+    format!(
+        "{}@{}",
+        zfs_dataset_name(&snapshot.dataset),
+        snapshot.snapshot_name
+    )
+}
+
+/// Checks if a dataset or snapshot exists, using `zfs list`
+pub async fn zfs_exists(name: &str) -> Result<bool, ZfsCommandError> {
+    let output = Command::new("zfs")
+        .args(["list", "-H", "-o", "name", name])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(ZfsCommandError::Spawn)?;
+    if output.status.success() {
+        return Ok(true);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("does not exist") {
+        Ok(false)
+    } else {
+        Err(ZfsCommandError::Failed(stderr.into_owned()))
+    }
+}
+
+/// Places a hold on the snapshot, so that it can't be destroyed while it is being backed up.
+/// Does nothing if the snapshot is already held.
+pub async fn zfs_hold(snapshot: &str) -> Result<(), ZfsCommandError> {
+    zfs(&["hold", HOLD_TAG, snapshot], Some("tag already exists")).await?;
+    Ok(())
+}
+
+/// Releases the hold placed by [`zfs_hold`]. Does nothing if the snapshot is not held.
+pub async fn zfs_release(snapshot: &str) -> Result<(), ZfsCommandError> {
+    zfs(&["release", HOLD_TAG, snapshot], Some("no such tag")).await?;
+    Ok(())
+}
+
+/// Lists the full names of the snapshots of the dataset
+pub async fn zfs_list_snapshots(dataset: &str) -> Result<Vec<String>, ZfsCommandError> {
+    Ok(zfs(
+        &[
+            "list", "-H", "-t", "snapshot", "-o", "name", "-d", "1", dataset,
+        ],
+        None,
+    )
+    .await?
+    .lines()
+    .map(ToOwned::to_owned)
+    .collect())
+}
+
+/// Lists the snapshots of the dataset which have a hold placed by [`zfs_hold`]
+pub async fn zfs_held_snapshots(dataset: &str) -> Result<Vec<String>, ZfsCommandError> {
+    let snapshots = zfs_list_snapshots(dataset).await?;
+    if snapshots.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = vec!["holds", "-H"];
+    args.extend(snapshots.iter().map(String::as_str));
+    let holds = zfs(&args, None).await?;
+    // Each line looks like `zpool/dataset@snapshot	tag	timestamp`
+    Ok(holds
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let snapshot = fields.next()?;
+            (fields.next()? == HOLD_TAG).then(|| snapshot.to_owned())
+        })
+        .collect())
+}
+
+/// Destroys a snapshot. The snapshot must not have any holds.
+pub async fn zfs_destroy_snapshot(snapshot: &str) -> Result<(), ZfsCommandError> {
+    zfs(&["destroy", snapshot], None).await?;
+    Ok(())
+}
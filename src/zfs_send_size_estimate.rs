@@ -0,0 +1,120 @@
+use std::{io, num::ParseIntError, process::Stdio};
+
+use tokio::process::Command;
+use zfs_wrapper::ZfsSnapshot;
+
+use crate::zfs_commands::zfs_snapshot_name;
+
+#[derive(Debug)]
+pub enum ZfsSendSizeEstimateError {
+    Spawn(io::Error),
+    /// `zfs send` exited unsuccessfully. Contains its stderr.
+    Failed(String),
+    /// Neither a `size` line nor any per-snapshot estimate was found in the output.
+    NoSize(String),
+    Parse(ParseIntError),
+}
+
+/// Runs `zfs send -w -n -P` (a dry run) with the same arguments that [`zfs_wrapper::zfs_send`] would use,
+/// and returns the estimated size of the stream, in bytes.
+/// Can be incremental from a previous snapshot.
+pub async fn zfs_send_size_estimate(
+    zfs_snapshot: ZfsSnapshot<'_>,
+    diff_from: Option<&str>,
+) -> Result<u64, ZfsSendSizeEstimateError> {
+    let mut command = Command::new("zfs");
+    command.args(["send", "-w", "-n", "-P"]);
+    if let Some(diff_from) = diff_from {
+        command.arg("-i").arg(format!("@{diff_from}"));
+    }
+    let output = command
+        .arg(zfs_snapshot_name(&zfs_snapshot))
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(ZfsSendSizeEstimateError::Spawn)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(ZfsSendSizeEstimateError::Failed(stderr.into_owned()));
+    }
+    // Newer versions of ZFS print the dry run info to stdout, while older versions print it to stderr
+    parse_size_estimate(&format!("{stdout}\n{stderr}"))
+}
+
+/// Parses the output of `zfs send -n -P`.
+///
+/// Recent versions of OpenZFS output something like
+/// ```text
+/// incremental	backup0	zpool/dataset@backup1	1234
+/// size	1234
+/// ```
+/// but some older versions only output the per-snapshot line, without the `size` total.
+fn parse_size_estimate(output: &str) -> Result<u64, ZfsSendSizeEstimateError> {
+    let lines = output
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|fields| !fields.is_empty())
+        .collect::<Vec<_>>();
+    if let Some(fields) = lines.iter().find(|fields| fields[0] == "size") {
+        return fields
+            .get(1)
+            .ok_or_else(|| ZfsSendSizeEstimateError::NoSize(output.to_owned()))?
+            .parse()
+            .map_err(ZfsSendSizeEstimateError::Parse);
+    }
+    let snapshot_estimates = lines
+        .iter()
+        .filter(|fields| matches!(fields[0], "full" | "incremental"))
+        .filter_map(|fields| fields.last())
+        .map(|size| size.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ZfsSendSizeEstimateError::Parse)?;
+    if snapshot_estimates.is_empty() {
+        return Err(ZfsSendSizeEstimateError::NoSize(output.to_owned()));
+    }
+    Ok(snapshot_estimates.into_iter().sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_full_estimate() {
+        let stdout = "full\tzpool/dataset@backup0\t4219000\nsize\t4219000\n";
+        assert_eq!(
+            parse_size_estimate(&format!("{stdout}\n")).unwrap(),
+            4219000
+        );
+    }
+
+    #[test]
+    fn parses_incremental_estimate() {
+        let stdout = "incremental\tbackup0\tzpool/dataset@backup1\t58264\nsize\t58264\n";
+        assert_eq!(parse_size_estimate(&format!("{stdout}\n")).unwrap(), 58264);
+    }
+
+    #[test]
+    fn parses_estimate_without_size_line() {
+        let stdout = "incremental\tbackup0\tzpool/dataset@backup1\t58264\n";
+        assert_eq!(parse_size_estimate(&format!("{stdout}\n")).unwrap(), 58264);
+    }
+
+    #[test]
+    fn parses_estimate_on_stderr() {
+        let stderr = "full\tzpool/dataset@backup0\t4219000\nsize\t4219000\n";
+        assert_eq!(
+            parse_size_estimate(&format!("\n{stderr}")).unwrap(),
+            4219000
+        );
+    }
+
+    #[test]
+    fn no_estimate_is_an_error() {
+        assert!(matches!(
+            parse_size_estimate("\n"),
+            Err(ZfsSendSizeEstimateError::NoSize(_))
+        ));
+    }
+}
@@ -14,6 +14,7 @@ temp_dir = "/tmp/backup_temp"
 chunk_size = 30_000
 file_0_name = "file_0.txt"
 file_1_name = "file_1.txt"
+file_2_name = "file_2.txt"
 
 # Initialize S3 server
 server.wait_for_unit("minio")
@@ -33,6 +34,19 @@ uploader.succeed(f'zfs-incremental-backup run --save-data-path {save_data_path}
 # Create a snapshot with another file
 uploader.succeed(f'touch /{zpool_name}/{dataset_name}/{file_1_name}')
 uploader.succeed(f'zfs-incremental-backup run --save-data-path {save_data_path} --temp-dir {temp_dir} --storage-class STANDARD --chunk-size {chunk_size} --dev --dev-endpoint http://server:9000')
+# Check the save data and the uploaded backups
+uploader.succeed(f'zfs-incremental-backup validate --save-data-path {save_data_path} --dev --dev-endpoint http://server:9000')
+uploader.succeed(f'zfs-incremental-backup audit --save-data-path {save_data_path} --chunk-size {chunk_size} --dev --dev-endpoint http://server:9000')
+# Holds are released after a snapshot is backed up
+uploader.succeed(f'test -z "$(zfs holds -H {zpool_name}/{dataset_name}@{snapshot_prefix}1)"')
+# Create a third snapshot, and only keep the last one
+uploader.succeed(f'touch /{zpool_name}/{dataset_name}/{file_2_name}')
+uploader.succeed(f'zfs-incremental-backup run --save-data-path {save_data_path} --temp-dir {temp_dir} --storage-class STANDARD --chunk-size {chunk_size} --keep-snapshots 1 --dev --dev-endpoint http://server:9000')
+uploader.fail(f'zfs list {zpool_name}/{dataset_name}@{snapshot_prefix}0')
+uploader.fail(f'zfs list {zpool_name}/{dataset_name}@{snapshot_prefix}1')
+uploader.succeed(f'zfs list {zpool_name}/{dataset_name}@{snapshot_prefix}2')
+# A missing save data file is a config error
+uploader.succeed(f'zfs-incremental-backup run --save-data-path /tmp/missing.ron --temp-dir {temp_dir} --storage-class STANDARD --chunk-size {chunk_size} --dev --dev-endpoint http://server:9000; test $? -eq 3')
 
 # Verify that the backup can be restored
 downloader.wait_for_unit("default.target")
@@ -47,3 +61,9 @@ downloader.fail(f'ls /{zpool_name}/{dataset_name}/{file_1_name}')
 downloader.succeed(f"mc cat minio/{bucket}/{object_prefix}backup0_backup1/0 | zfs receive {zpool_name}/{dataset_name}")
 downloader.succeed(f'ls /{zpool_name}/{dataset_name}/{file_0_name}')
 downloader.succeed(f'ls /{zpool_name}/{dataset_name}/{file_1_name}')
+# Verify that the receive command restores the same snapshots
+downloader.succeed(f'zfs-incremental-backup receive --bucket {bucket} --object-prefix "{object_prefix}" --snapshot-prefix {snapshot_prefix} --snapshot 1 --target {zpool_name}/received --dev --dev-endpoint http://server:9000')
+downloader.succeed(f'ls /{zpool_name}/received/{file_0_name}')
+downloader.succeed(f'ls /{zpool_name}/received/{file_1_name}')
+downloader.succeed(f'zfs list {zpool_name}/received@{snapshot_prefix}0')
+downloader.succeed(f'zfs list {zpool_name}/received@{snapshot_prefix}1')